@@ -0,0 +1,414 @@
+//! Compute the integrity checksums of the package sources.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use crate::config::{ArchConfig, expand_source, is_remote, is_vcs, source_filename};
+use crate::makepkg;
+use crate::pkgbuild;
+
+
+/// The integrity checks makepkg supports, each one computed by its coreutils `<algo>sum` tool
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumAlgo {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+    B2,
+}
+
+impl ChecksumAlgo {
+    pub const ALL: &'static [ChecksumAlgo] = &[
+        ChecksumAlgo::Md5,
+        ChecksumAlgo::Sha1,
+        ChecksumAlgo::Sha224,
+        ChecksumAlgo::Sha256,
+        ChecksumAlgo::Sha384,
+        ChecksumAlgo::Sha512,
+        ChecksumAlgo::B2,
+    ];
+
+    /// The algorithm name as in `--checksum-algo`, e.g. `sha256`
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChecksumAlgo::Md5 => "md5",
+            ChecksumAlgo::Sha1 => "sha1",
+            ChecksumAlgo::Sha224 => "sha224",
+            ChecksumAlgo::Sha256 => "sha256",
+            ChecksumAlgo::Sha384 => "sha384",
+            ChecksumAlgo::Sha512 => "sha512",
+            ChecksumAlgo::B2 => "b2",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ChecksumAlgo> {
+        ChecksumAlgo::ALL.iter().copied().find(|algo| algo.name() == name)
+    }
+
+    /// The PKGBUILD array holding this algorithm's checksums, e.g. `sha256sums`
+    pub fn array(&self) -> String {
+        format!("{}sums", self.name())
+    }
+
+    /// Hash `file` with the `<algo>sum` tool
+    pub fn hash_file(&self, file: &Path) -> Result<String, String> {
+        let tool = format!("{}sum", self.name());
+        let output = Command::new(&tool)
+                             .arg(file)
+                             .output()
+                             .map_err(|e| format!("could not run {}: {}", tool, e))?;
+
+        self.parse_output(output)
+    }
+
+    /// Hash `data` with the `<algo>sum` tool reading it from stdin
+    pub fn hash_bytes(&self, data: &[u8]) -> Result<String, String> {
+        let tool = format!("{}sum", self.name());
+        let mut child = Command::new(&tool)
+                                .stdin(Stdio::piped())
+                                .stdout(Stdio::piped())
+                                .stderr(Stdio::piped())
+                                .spawn()
+                                .map_err(|e| format!("could not run {}: {}", tool, e))?;
+        child.stdin.take().unwrap()
+                   .write_all(data)
+                   .map_err(|e| format!("could not write to {}: {}", tool, e))?;
+        let output = child.wait_with_output()
+                          .map_err(|e| format!("could not run {}: {}", tool, e))?;
+
+        self.parse_output(output)
+    }
+
+    /// The hash at the start of the tool's output
+    fn parse_output(&self, output: Output) -> Result<String, String> {
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+
+        String::from_utf8_lossy(&output.stdout)
+            .split_whitespace()
+            .next()
+            .map(|hash| hash.to_string())
+            .ok_or_else(|| format!("no output from {}sum", self.name()))
+    }
+}
+
+/// Fill the `algo` checksum array from the sources next to Cargo.toml and empty the others.
+///
+/// Local sources are looked up with `$pkgname` and `$pkgver` expanded, the array keeps them as written.
+/// VCS sources are checked out, they get `SKIP`.
+/// Other remote sources are downloaded when `download` is set, see `remote_file`,
+/// otherwise, or when the download fails, they can't be hashed and keep the sum the array
+/// already pins for them, or get `SKIP` and a warning, like directories.
+pub fn compute_checksums(arch_config: &mut ArchConfig, algo: ChecksumAlgo, download: bool) {
+    let mut sums = vec![];
+    let mut pinned = arch_config.checksums_mut(&algo.array()).unwrap().clone();
+    if pinned.len() != arch_config.source.len() {
+        pinned.clear();
+    }
+    let download_dir = std::env::temp_dir().join(format!("cargo-arch-download-{}", std::process::id()));
+
+    for (i, source) in arch_config.source.clone().into_iter().enumerate() {
+        if is_vcs(&source) {
+            sums.push("SKIP".to_string());
+            continue;
+        }
+
+        let expanded = expand_source(&source, &arch_config.pkgname, &arch_config.pkgver);
+        let file = if !is_remote(&source) {
+            arch_config.manifest_dir.join(source_filename(&expanded))
+        } else if download {
+            match remote_file(&expanded, &download_dir) {
+                Ok(file) => file,
+                Err(e) => {
+                    arch_config.diagnostics.error("source", "source-download", e);
+                    sums.push(pinned.get(i).cloned().unwrap_or_else(|| "SKIP".to_string()));
+                    continue;
+                },
+            }
+        } else if let Some(sum) = pinned.get(i).filter(|sum| *sum != "SKIP") {
+            log::info!("keeping the pinned checksum of remote source `{}`, pass --download to recompute it", source);
+            sums.push(sum.clone());
+            continue;
+        } else {
+            arch_config.diagnostics.warning(
+                "source", "remote-source-skipped",
+                format!("can't compute the checksum of remote source `{}` without --download, using SKIP", source));
+            sums.push("SKIP".to_string());
+            continue;
+        };
+
+        if file.is_dir() {
+            arch_config.diagnostics.warning(
+                "source", "directory-source",
+                format!("`{}` is a directory, makepkg links it into $srcdir as is and can't verify it, using SKIP",
+                        source));
+            sums.push("SKIP".to_string());
+            continue;
+        }
+
+        match algo.hash_file(&file) {
+            Ok(hash) => sums.push(hash),
+            Err(e) => {
+                arch_config.diagnostics.error(
+                    "source", "source-checksum",
+                    format!("can't compute the checksum of `{}`: {}", source, e));
+                sums.push("SKIP".to_string());
+            },
+        }
+    }
+    let _ = fs::remove_dir_all(&download_dir);
+
+    for other in ChecksumAlgo::ALL {
+        arch_config.checksums_mut(&other.array()).unwrap().clear();
+    }
+    *arch_config.checksums_mut(&algo.array()).unwrap() = sums;
+}
+
+/// A remote source on disk, from makepkg's `SRCDEST` cache when it's already there,
+/// else downloaded into `dir`
+pub fn remote_file(source: &str, dir: &Path) -> Result<PathBuf, String> {
+    if let Some(srcdest) = std::env::var_os("SRCDEST").filter(|srcdest| !srcdest.is_empty()) {
+        let cached = Path::new(&srcdest).join(source_filename(source));
+        if cached.is_file() {
+            log::info!("using {} from SRCDEST", cached.display());
+            return Ok(cached);
+        }
+    }
+
+    fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+    download(source, dir)
+}
+
+/// Download a remote source into `dir` with curl, as makepkg names it
+pub fn download(source: &str, dir: &Path) -> Result<PathBuf, String> {
+    let url = source.split_once("::").map(|(_, url)| url).unwrap_or(source);
+    let file = dir.join(source_filename(source));
+
+    let output = Command::new("curl")
+                         .args(["--fail", "--silent", "--show-error", "--location", "--output"])
+                         .arg(&file)
+                         .arg(url)
+                         .output()
+                         .map_err(|e| format!("could not run curl: {}", e))?;
+
+    if output.status.success() {
+        Ok(file)
+    } else {
+        Err(format!("could not download {}: {}", url, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// `updsums`, refresh the checksum arrays of an existing PKGBUILD in place, like `updpkgsums`.
+///
+/// Every non-empty `<algo>sums` array is recomputed, `sha256sums` when there is none.
+/// Local sources are read next to the PKGBUILD, remote ones downloaded to a temporary directory.
+pub fn update_pkgbuild_checksums(pkgbuild_path: &Path) -> Result<(), String> {
+    let sources = makepkg::read_array(pkgbuild_path, "source").unwrap_or_default();
+    let dir = match pkgbuild_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+
+    let mut algos = ChecksumAlgo::ALL.iter()
+                                     .copied()
+                                     .filter(|algo| {
+                                         makepkg::read_array(pkgbuild_path, &algo.array())
+                                             .map(|sums| !sums.is_empty())
+                                             .unwrap_or(false)
+                                     })
+                                     .collect::<Vec<ChecksumAlgo>>();
+    if algos.is_empty() {
+        algos.push(ChecksumAlgo::Sha256);
+    }
+
+    let download_dir = std::env::temp_dir().join(format!("cargo-arch-updsums-{}", std::process::id()));
+    fs::create_dir_all(&download_dir).map_err(|e| e.to_string())?;
+
+    let update = || {
+        let mut files = vec![];
+        for source in &sources {
+            files.push(if is_vcs(source) {
+                None
+            } else if is_remote(source) {
+                Some(remote_file(source, &download_dir)?)
+            } else {
+                Some(dir.join(source_filename(source)))
+            });
+        }
+
+        let mut content = fs::read_to_string(pkgbuild_path).map_err(|e| e.to_string())?;
+        for algo in &algos {
+            let mut sums = vec![];
+            for file in &files {
+                sums.push(match file {
+                    Some(file) if file.is_file() => algo.hash_file(file)?,
+                    _ => "SKIP".to_string(),
+                });
+            }
+            content = pkgbuild::replace_array(&content, &algo.array(), &sums)?;
+            log::info!("updated {} in {}", algo.array(), pkgbuild_path.display());
+        }
+
+        fs::write(pkgbuild_path, content).map_err(|e| e.to_string())
+    };
+
+    let result = update();
+    let _ = fs::remove_dir_all(&download_dir);
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Response, TempDir, http_server, manifest, with_env};
+
+    const SUM_A: &str = "87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf25c7";
+    const SUM_B: &str = "0263829989b6fd954f72baaf2fc64bc2e2f01d692d4de72986ea808f6e99813f";
+    const B2_A: &str = "bedfbb90d858c2d67b7ee8f7523be3d3b54004ef9e4f02f2ad79a1d05bfdfe49\
+                        b81e3c92ebf99b504102b6bf003fa342587f5b3124c205f55204e8c4b4ce7d7c";
+
+    #[test]
+    fn download_remote_sources() {
+        let url = http_server(vec![("/a.txt", Response::Ok(b"a\n".to_vec())),
+                                   ("/moved.txt", Response::Redirect("/a.txt"))]);
+        let srcdest = TempDir::new();
+        srcdest.write("cached.txt", "b\n");
+        let sources = ["a.txt", "moved.txt", "missing.txt", "cached.txt"].iter()
+                                                                         .map(|file| format!("\"{}/{}\"", url, file))
+                                                                         .collect::<Vec<String>>();
+        let mut arch_config = ArchConfig::from_manifest_str(&manifest("foo", &format!("source = [{}]\n", sources.join(", "))), None);
+
+        with_env(&[("SRCDEST", Some(srcdest.path().as_os_str()))], || {
+            compute_checksums(&mut arch_config, ChecksumAlgo::Sha256, true);
+        });
+
+        assert_eq!(arch_config.sha256sums, [SUM_A, SUM_A, "SKIP", SUM_B]);
+        let errors = arch_config.diagnostics.iter()
+                                            .filter(|diagnostic| diagnostic.check == "source-download")
+                                            .map(|diagnostic| diagnostic.message.as_str())
+                                            .collect::<Vec<&str>>();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("missing.txt") && errors[0].contains("404"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn remote_sources_need_download() {
+        let mut arch_config = ArchConfig::from_manifest_str(&manifest("foo", "source = [\"https://example.org/a.txt\"]\n"), None);
+
+        compute_checksums(&mut arch_config, ChecksumAlgo::Sha256, false);
+
+        assert_eq!(arch_config.sha256sums, ["SKIP"]);
+        assert!(arch_config.diagnostics.iter().any(|diagnostic| diagnostic.check == "remote-source-skipped"));
+    }
+
+
+    /// A crate with the local source `a.txt`, its checksums computed with `algo`
+    fn local_checksums(algo: ChecksumAlgo) -> ArchConfig {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", "source = [\"a.txt\"]\nsha256sums = [\"stale\"]\n"));
+        dir.write("a.txt", "a\n");
+        let mut arch_config = ArchConfig::new(dir.path().to_str(), None);
+        compute_checksums(&mut arch_config, algo, false);
+        arch_config
+    }
+
+    #[test]
+    fn compute_sha512sums() {
+        let arch_config = local_checksums(ChecksumAlgo::Sha512);
+
+        assert_eq!(arch_config.sha512sums, ["162b0b32f02482d5aca0a7c93dd03ceac3acd7e410a5f18f3fb990fc958ae0df\
+                                             6f32233b91831eaf99ca581a8c4ddf9c8ba315ac482db6d4ea01cc7884a635be"]);
+        assert!(arch_config.sha256sums.is_empty());
+    }
+
+    #[test]
+    fn compute_b2sums() {
+        let arch_config = local_checksums(ChecksumAlgo::B2);
+
+        assert_eq!(arch_config.b2sums, [B2_A]);
+        assert!(arch_config.sha256sums.is_empty());
+    }
+
+
+    #[test]
+    fn directory_sources_are_skipped() {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", "source = [\"assets\", \"a.txt\"]\n"));
+        dir.write("assets/icon.svg", "<svg/>\n");
+        dir.write("a.txt", "a\n");
+        let mut arch_config = ArchConfig::new(dir.path().to_str(), None);
+
+        compute_checksums(&mut arch_config, ChecksumAlgo::Sha256, false);
+
+        assert_eq!(arch_config.sha256sums, ["SKIP", SUM_A]);
+        assert!(arch_config.diagnostics.iter().any(|diagnostic| diagnostic.check == "directory-source"));
+        assert!(!arch_config.validate().iter().any(|diagnostic| diagnostic.check == "missing-local-source"));
+    }
+
+    #[test]
+    fn missing_local_sources_are_reported() {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", "source = [\"assets\"]\n"));
+
+        let arch_config = ArchConfig::new(dir.path().to_str(), None);
+
+        assert!(arch_config.validate().iter().any(|diagnostic| diagnostic.check == "missing-local-source"));
+    }
+
+
+    #[test]
+    fn update_the_checksums_of_a_pkgbuild() {
+        let dir = TempDir::new();
+        dir.write("foo-1.0.txt", "a\n");
+        let pkgbuild = dir.write("PKGBUILD", "pkgname=foo\npkgver=1.0\nsource=(\"$pkgname-$pkgver.txt\")\n\
+                                              b2sums=(\n  'stale'\n)\n\nbuild() {\n  true\n}\n");
+
+        update_pkgbuild_checksums(&pkgbuild).unwrap();
+
+        assert_eq!(fs::read_to_string(&pkgbuild).unwrap(),
+                   format!("pkgname=foo\npkgver=1.0\nsource=(\"$pkgname-$pkgver.txt\")\n\
+                            b2sums=('{}')\n\nbuild() {{\n  true\n}}\n", B2_A));
+    }
+
+
+    #[test]
+    fn sources_are_found_with_pkgver_expanded() {
+        let url = http_server(vec![("/foo-1.0.0.tar.gz", Response::Ok(b"b\n".to_vec()))]);
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", &format!("source = [\"$pkgname-$pkgver.txt\", \
+                                                                     \"{}/${{pkgname}}-${{pkgver}}.tar.gz\"]\n", url)));
+        dir.write("foo-1.0.0.txt", "a\n");
+        let srcdest = TempDir::new();
+        let mut arch_config = ArchConfig::new(dir.path().to_str(), None);
+
+        with_env(&[("SRCDEST", Some(srcdest.path().as_os_str()))], || {
+            compute_checksums(&mut arch_config, ChecksumAlgo::Sha256, true);
+        });
+
+        assert_eq!(arch_config.sha256sums, [SUM_A, SUM_B]);
+        assert!(arch_config.render_pkgbuild()
+                           .contains(&format!("source=(\"$pkgname-$pkgver.txt\", \"{}/${{pkgname}}-${{pkgver}}.tar.gz\")\n", url)));
+    }
+
+
+    #[test]
+    fn vcs_sources_get_skip_at_their_index() {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", "source = [\"a.txt\", \"foo::git+https://example.org/foo.git#tag=v1\", \"b.txt\"]\n"));
+        dir.write("a.txt", "a\n");
+        dir.write("b.txt", "b\n");
+        let mut arch_config = ArchConfig::new(dir.path().to_str(), None);
+
+        compute_checksums(&mut arch_config, ChecksumAlgo::Sha256, false);
+
+        assert_eq!(arch_config.sha256sums, [SUM_A, "SKIP", SUM_B]);
+        assert!(arch_config.diagnostics.is_empty());
+    }
+}
@@ -1,29 +1,35 @@
 //! Arch Linux's package config
 
-use std::fs::File;
-use std::io::prelude::*;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
 
-use toml;
-
-use super::core::{Cargo, ToPackageConfig, GeneratePackageConfig};
-use super::meta::CargoMetadata;
+use super::core::{Cargo, CargoAuthor, ToPackageConfig, GeneratePackageConfig, cargo_target_dir, manifest_dir,
+                  pkgbuild_target_dir};
+use super::glob::expand_glob;
+use super::template::{template, detect_template};
+use super::validate::Diagnostics;
 
 
 /// data in `[package.metadata.arch]` section
 #[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct CargoArch {
     /// The maintainers of the package
-    pub maintainers: Option<Vec<String>>,
+    pub maintainers: Option<Vec<CargoAuthor>>,
     /// The name of the package.
     pub pkgname: Option<String>,
     /// The version of the software as released from the author.
     pub pkgver: Option<String>,
     /// This is the release number specific to the Arch Linux release.
     pub pkgrel: Option<String>,
+    /// `pkgrel` when neither it nor the `CARGO_ARCH_PKGREL` environment variable is set, instead of `1`.
+    pub default_pkgrel: Option<String>,
     /// Used to force the package to be seen as newer than any previous versions with a lower epoch,
     /// even if the version number would normally not trigger such an upgrade.
     pub epoch: Option<String>,
     /// This should be a brief description of the package and its functionality.
+    #[serde(alias = "descr", alias = "description")]
     pub pkgdesc: Option<String>,
     /// This field contains a URL that is associated with the software being packaged.
     /// This is typically the project’s web site.
@@ -35,9 +41,12 @@ pub struct CargoArch {
     /// Specifies a changelog file that is to be included in the package.
     pub changelog: Option<String>,
     /// An array of source files required to build the package.
-    pub source: Option<Vec<String>>,
+    /// An entry can also be a table carrying its checksums, see `CargoSource`.
+    pub source: Option<Vec<CargoSource>>,
     /// An array of PGP fingerprints.
     pub validpgpkeys: Option<Vec<String>>,
+    /// The owner of each `validpgpkeys` fingerprint, written as a comment after it.
+    pub pgpkeys: Option<BTreeMap<String, String>>,
     /// An array of file names corresponding to those from the source array.
     pub noextract: Option<Vec<String>>,
     /// This array contains an MD5 hash for every source file specified in the source array (in the same order).
@@ -45,28 +54,46 @@ pub struct CargoArch {
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha1sums: Option<Vec<String>>,
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
+    pub sha224sums: Option<Vec<String>>,
+    /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha256sums: Option<Vec<String>>,
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha384sums: Option<Vec<String>>,
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha512sums: Option<Vec<String>>,
+    /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
+    pub b2sums: Option<Vec<String>>,
     /// An array of symbolic names that represent groups of packages,
     /// allowing you to install multiple packages by requesting a single target.
     pub groups: Option<Vec<String>>,
     /// Defines on which architectures the given package is available.
+    /// The comma separated `CARGO_ARCH_ARCH` environment variable takes precedence.
     pub arch: Option<Vec<String>>,
     /// An array of file names, without preceding slashes,
     /// that should be backed up if the package is removed or upgraded.
     pub backup: Option<Vec<String>>,
+    /// Globs like `etc/**/*.conf` matched against the files under `backup_dir`,
+    /// each file found is added to `backup`.
+    pub backup_glob: Option<Vec<String>>,
+    /// The directory of the crate mirroring the installed tree `backup_glob` is matched in, defaults to the crate.
+    pub backup_dir: Option<String>,
+    /// Build for multilib, the `depends` named in `multilib_libs` get the `lib32-` prefix when `arch` is `x86_64`.
+    pub multilib: Option<bool>,
+    /// The libraries `multilib` prefixes, like `["zlib", "openssl"]`.
+    pub multilib_libs: Option<Vec<String>>,
     /// An array of packages this package depends on to run.
+    #[serde(alias = "deps")]
     pub depends: Option<Vec<String>>,
     /// An array of packages this package depends on to build but are not needed at runtime.
+    #[serde(alias = "makedeps")]
     pub makedepends: Option<Vec<String>>,
     /// An array of packages this package depends on to run its test suite but are not needed at runtime.
     pub checkdepends: Option<Vec<String>>,
     /// An array of packages (and accompanying reasons) that are not essential for base functionality,
     /// but may be necessary to make full use of the contents of this package.
     pub optdepends: Option<Vec<String>>,
+    /// Write `optdepends` one entry per line, with the reasons aligned.
+    pub wrap_optdepends: Option<bool>,
     /// An array of packages that will conflict with this package.
     pub conflicts: Option<Vec<String>>,
     /// An array of "virtual provisions" this package provides.
@@ -75,6 +102,299 @@ pub struct CargoArch {
     pub replaces: Option<Vec<String>>,
     /// This array allows you to override some of makepkg’s default behavior when building packages.
     pub options: Option<Vec<String>>,
+    /// Append a `# vim:set ts=<indent> sw=<indent> et:` modeline at the end of the PKGBUILD.
+    pub modeline: Option<bool>,
+    /// Build `pkgdesc` from `package.keywords` and `package.categories` when the description is empty.
+    pub pkgdesc_from_keywords: Option<bool>,
+    /// Warn when `pkgdesc` is longer than this many characters, defaults to 80.
+    pub pkgdesc_max_length: Option<usize>,
+    /// Which `[package]` fields `url` falls back to, in order of preference.
+    /// Known values are `homepage`, `repository` and `documentation`.
+    pub url_from: Option<Vec<String>>,
+    /// This is a VCS package, its pkgver is computed by `pkgver()` from the git history.
+    pub vcs: Option<bool>,
+    /// Append the template's build functions after the variables, defaults to true.
+    pub include_template: Option<bool>,
+    /// Install the published crate with `cargo install` from the registry, the `cargo-install` template.
+    pub cargo_install: Option<bool>,
+    /// Spaces per indentation level inside the build functions, defaults to 2.
+    pub indent: Option<usize>,
+    /// Line endings of the PKGBUILD, `lf` (default) or `crlf`.
+    pub newline: Option<String>,
+    /// Vendor the crate dependencies in `prepare()` and build offline.
+    /// The source must ship the `Cargo.lock`, `cargo vendor --locked` refuses to run without it.
+    pub vendor: Option<bool>,
+    /// Apply the `.patch` and `.diff` sources in `prepare()` with `patch -Np1`, in source order.
+    pub auto_apply_patches: Option<bool>,
+    /// Run `cargo fetch --locked` in `prepare()`, failing there on a stale `Cargo.lock` rather than in `build()`.
+    pub fetch_in_prepare: Option<bool>,
+    /// Features the build enables with `--features`.
+    pub features: Option<Vec<String>>,
+    /// Build without the `default` feature.
+    pub no_default_features: Option<bool>,
+    /// Packages added to `depends` when the build enables a feature, like `gui = ["gtk3"]`.
+    pub feature_depends: Option<BTreeMap<String, Vec<String>>>,
+    /// Where and how `package()` installs the binaries.
+    pub bin_install: Option<CargoBinInstall>,
+    /// Other files `package()` installs, like desktop files and icons.
+    pub install_files: Option<Vec<CargoInstallFile>>,
+    /// A sysusers.d config in the crate, installed as `usr/lib/sysusers.d/<pkgname>.conf`
+    /// and applied by the generated `install` script.
+    pub sysusers: Option<String>,
+    /// A tmpfiles.d config in the crate, installed as `usr/lib/tmpfiles.d/<pkgname>.conf`
+    /// and applied by the generated `install` script.
+    pub tmpfiles: Option<String>,
+    /// Directory under `$srcdir` the sources extract to, like `$pkgname-$pkgver`,
+    /// the build functions `cd` into it instead of the PKGBUILD's directory.
+    pub srcdir: Option<String>,
+    /// Add `cargo`, or `rust>=<rust-version>` when the crate has an MSRV, to `makedepends`, defaults to true.
+    pub auto_makedepends: Option<bool>,
+    /// Add the soname of the built cdylib to `provides`, like `libfoo.so=1-64`.
+    pub cdylib_provides: Option<bool>,
+    /// Add the names of the binaries to `provides`.
+    pub provides_bins: Option<bool>,
+    /// Add `<base>=$pkgver` to `provides`, base being pkgname without its version suffix like `foo` for `foo1.0`.
+    pub versioned_provides: Option<bool>,
+    /// In `--workspace` mode, make the other members installing a binary of the same name conflict with this one.
+    pub detect_bin_conflicts: Option<bool>,
+    /// Add `debug` to `options`, or `!debug` when false, so makepkg builds a `<pkgname>-debug` package or doesn't.
+    pub debug: Option<bool>,
+    /// Which crate dependencies `depends` is derived from through the depmaps:
+    /// `none`, `explicit` (only the `depends` list), `all-deps` or `runtime-features` (default).
+    pub depends_from: Option<String>,
+    /// Pacman packages standing in for crate dependencies, like `openssl-sys = "openssl"`,
+    /// added to `depends` when the crate depends on them.
+    pub depmap: Option<BTreeMap<String, String>>,
+}
+
+/// The alternative names `[package.metadata.arch]` accepts, with a warning, for its keys
+pub const ALIASES: &[(&str, &str)] = &[
+    ("deps", "depends"),
+    ("makedeps", "makedepends"),
+    ("descr", "pkgdesc"),
+    ("description", "pkgdesc"),
+];
+
+/// Every key of `[package.metadata.arch]` with its type and a one line description, in `CargoArch` order,
+/// `cargo arch list-fields` prints them.
+/// Keep it in sync with `CargoArch`, a debug build of `list-fields` checks it is.
+pub const METADATA_FIELDS: &[(&str, &str, &str)] = &[
+    ("maintainers", "array of authors", "The maintainers of the package"),
+    ("pkgname", "string", "The name of the package"),
+    ("pkgver", "string", "The version of the software as released from the author"),
+    ("pkgrel", "string", "The release number specific to the Arch Linux release"),
+    ("default_pkgrel", "string", "`pkgrel` when neither it nor `CARGO_ARCH_PKGREL` is set, instead of `1`"),
+    ("epoch", "string", "Forces the package to be seen as newer than versions with a lower epoch"),
+    ("pkgdesc", "string", "A brief description of the package and its functionality"),
+    ("url", "string", "A URL associated with the software, typically its web site"),
+    ("license", "array of strings", "The licenses that apply to the package"),
+    ("install", "string", "A special install script included in the package"),
+    ("changelog", "string", "A changelog file included in the package"),
+    ("source", "array of strings or tables", "An array of source files required to build the package, entries can be tables carrying their checksums"),
+    ("validpgpkeys", "array of strings", "PGP fingerprints verifying the signed sources"),
+    ("pgpkeys", "table of strings", "The owner of each `validpgpkeys` fingerprint, written as a comment after it"),
+    ("noextract", "array of strings", "Source files makepkg must not extract"),
+    ("md5sums", "array of strings", "An MD5 hash for every source file, in the same order"),
+    ("sha1sums", "array of strings", "Like md5sums, with SHA-1 hashes"),
+    ("sha224sums", "array of strings", "Like md5sums, with SHA-224 hashes"),
+    ("sha256sums", "array of strings", "Like md5sums, with SHA-256 hashes"),
+    ("sha384sums", "array of strings", "Like md5sums, with SHA-384 hashes"),
+    ("sha512sums", "array of strings", "Like md5sums, with SHA-512 hashes"),
+    ("b2sums", "array of strings", "Like md5sums, with BLAKE2 hashes"),
+    ("groups", "array of strings", "Symbolic names of package groups the package belongs to"),
+    ("arch", "array of strings", "The architectures the package is available on, `CARGO_ARCH_ARCH` takes precedence"),
+    ("backup", "array of strings", "Files, without leading slashes, backed up when the package is removed or upgraded"),
+    ("backup_glob", "array of strings", "Globs like `etc/**/*.conf` whose matches are added to `backup`"),
+    ("backup_dir", "string", "The directory of the crate `backup_glob` is matched in, defaults to the crate"),
+    ("multilib", "bool", "Prefix the `multilib_libs` depends with `lib32-` when `arch` is `x86_64`"),
+    ("multilib_libs", "array of strings", "The libraries `multilib` prefixes, like `[\"zlib\", \"openssl\"]`"),
+    ("depends", "array of strings", "Packages this package depends on to run"),
+    ("makedepends", "array of strings", "Packages needed to build but not at runtime"),
+    ("checkdepends", "array of strings", "Packages needed to run the test suite but not at runtime"),
+    ("optdepends", "array of strings", "Packages not essential for base functionality, with the reasons to install them"),
+    ("wrap_optdepends", "bool", "Write `optdepends` one entry per line, with the reasons aligned"),
+    ("conflicts", "array of strings", "Packages conflicting with this package"),
+    ("provides", "array of strings", "Virtual provisions this package provides"),
+    ("replaces", "array of strings", "Packages this package replaces"),
+    ("options", "array of strings", "Overrides of makepkg's default behavior"),
+    ("modeline", "bool", "Append a `# vim:set ts=<indent> sw=<indent> et:` modeline at the end of the PKGBUILD"),
+    ("pkgdesc_from_keywords", "bool", "Build `pkgdesc` from `package.keywords` and `package.categories` when the description is empty"),
+    ("pkgdesc_max_length", "integer", "Warn when `pkgdesc` is longer than this many characters, defaults to 80"),
+    ("url_from", "array of strings", "Which `[package]` fields `url` falls back to, in order of preference"),
+    ("vcs", "bool", "A VCS package, its pkgver is computed by `pkgver()` from the git history"),
+    ("include_template", "bool", "Append the template's build functions after the variables, defaults to true"),
+    ("cargo_install", "bool", "Install the published crate with `cargo install` from the registry, the `cargo-install` template"),
+    ("indent", "integer", "Spaces per indentation level inside the build functions, defaults to 2"),
+    ("newline", "string", "Line endings of the PKGBUILD, `lf` (default) or `crlf`"),
+    ("vendor", "bool", "Vendor the crate dependencies in `prepare()` and build offline"),
+    ("auto_apply_patches", "bool", "Apply the `.patch` and `.diff` sources in `prepare()` with `patch -Np1`, in source order"),
+    ("fetch_in_prepare", "bool", "Run `cargo fetch --locked` in `prepare()`, failing early on a stale `Cargo.lock`"),
+    ("features", "array of strings", "Features the build enables with `--features`"),
+    ("no_default_features", "bool", "Build without the `default` feature"),
+    ("feature_depends", "table of arrays", "Packages added to `depends` when the build enables a feature, like `gui = [\"gtk3\"]`"),
+    ("bin_install", "table", "Where and how `package()` installs the binaries"),
+    ("install_files", "array of tables", "Other files `package()` installs, like desktop files and icons"),
+    ("sysusers", "string", "A sysusers.d config installed and applied by the generated install script"),
+    ("tmpfiles", "string", "A tmpfiles.d config installed and applied by the generated install script"),
+    ("srcdir", "string", "Directory under `$srcdir` the sources extract to, the build functions `cd` into it"),
+    ("auto_makedepends", "bool", "Add `cargo`, or `rust>=<rust-version>` with an MSRV, to `makedepends`, defaults to true"),
+    ("cdylib_provides", "bool", "Add the soname of the built cdylib to `provides`, like `libfoo.so=1-64`"),
+    ("provides_bins", "bool", "Add the names of the binaries to `provides`"),
+    ("versioned_provides", "bool", "Add `<base>=$pkgver` to `provides`, base being pkgname without its version suffix"),
+    ("detect_bin_conflicts", "bool", "In `--workspace` mode, conflict with the members installing a binary of the same name"),
+    ("debug", "bool", "Add `debug` to `options`, or `!debug` when false"),
+    ("depends_from", "string", "Which crate dependencies `depends` is derived from: `none`, `explicit`, `all-deps` or `runtime-features`"),
+    ("depmap", "table of strings", "Pacman packages standing in for crate dependencies, like `openssl-sys = \"openssl\"`"),
+];
+
+/// data in `[package.metadata.arch.bin_install]` section
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CargoBinInstall {
+    /// Directory under `$pkgdir`, defaults to `usr/bin`.
+    pub dir: Option<String>,
+    /// Octal permissions, as a string like `"750"`, defaults to `755`.
+    pub mode: Option<String>,
+}
+
+/// How much of `depends` is derived from the crate dependencies
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DependsFrom {
+    /// only the `depends` list, not even `feature_depends`
+    None,
+    /// only what the metadata lists, `depends` and `feature_depends`, no crate dependency is mapped
+    Explicit,
+    /// every crate dependency, optional ones included
+    AllDeps,
+    /// the crate dependencies a default build links
+    RuntimeFeatures,
+}
+
+impl DependsFrom {
+    pub fn from_name(name: &str) -> Option<DependsFrom> {
+        match name {
+            "none" => Some(DependsFrom::None),
+            "explicit" => Some(DependsFrom::Explicit),
+            "all-deps" => Some(DependsFrom::AllDeps),
+            "runtime-features" => Some(DependsFrom::RuntimeFeatures),
+            _ => None,
+        }
+    }
+}
+
+/// an entry in `source`, either a plain source or a `[[package.metadata.arch.source]]` table
+/// keeping its checksums next to it
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum CargoSource {
+    Plain(String),
+    Detailed {
+        url: String,
+        /// The file name makepkg saves it as, the `rename::` prefix.
+        rename: Option<String>,
+        md5: Option<String>,
+        sha1: Option<String>,
+        sha224: Option<String>,
+        sha256: Option<String>,
+        sha384: Option<String>,
+        sha512: Option<String>,
+        b2: Option<String>,
+    },
+}
+
+impl CargoSource {
+    /// The `source` array entry
+    pub fn entry(&self) -> String {
+        match self {
+            CargoSource::Plain(source) => source.clone(),
+            CargoSource::Detailed { url, rename: Some(rename), .. } => format!("{}::{}", rename, url),
+            CargoSource::Detailed { url, .. } => url.clone(),
+        }
+    }
+
+    /// The checksum of a table entry for the `array`, like `sha256sums`
+    fn checksum(&self, array: &str) -> Option<&String> {
+        match self {
+            CargoSource::Plain(_) => None,
+            CargoSource::Detailed { md5, sha1, sha224, sha256, sha384, sha512, b2, .. } => match array {
+                "md5sums" => md5.as_ref(),
+                "sha1sums" => sha1.as_ref(),
+                "sha224sums" => sha224.as_ref(),
+                "sha256sums" => sha256.as_ref(),
+                "sha384sums" => sha384.as_ref(),
+                "sha512sums" => sha512.as_ref(),
+                "b2sums" => b2.as_ref(),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// The `array` checksums of `sources`: the flat `sums` as given when no table entry has one of its kind,
+/// otherwise one per source, the table's own or the flat array's at the same position, else `SKIP`
+fn merge_checksums(sources: &[CargoSource], array: &str, sums: Option<&Vec<String>>) -> Vec<String> {
+    let sums = sums.cloned().unwrap_or_default();
+    if !sources.iter().any(|source| source.checksum(array).is_some()) {
+        return sums;
+    }
+    sources.iter()
+           .enumerate()
+           .map(|(i, source)| {
+               source.checksum(array)
+                     .or_else(|| sums.get(i))
+                     .cloned()
+                     .unwrap_or_else(|| "SKIP".to_string())
+           })
+           .collect()
+}
+
+/// an entry in `[[package.metadata.arch.install_files]]`
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CargoInstallFile {
+    /// Path in the crate.
+    pub src: String,
+    /// Path under `$pkgdir`, without a leading slash.
+    pub dest: String,
+    /// Octal permissions, defaults to `644`.
+    pub mode: Option<String>,
+}
+
+/// Where the value of a resolved PKGBUILD field comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FieldSource {
+    /// set in `[package.metadata.arch]`
+    Metadata,
+    /// taken from the `[package]` section
+    CargoFallback,
+    /// cargo-arch's built-in default
+    Default,
+    /// a `CARGO_ARCH_*` environment variable
+    Environment,
+    /// a command line option, like `--pkgrel` or `--target`
+    CommandLine,
+}
+
+/// The PKGBUILD variables, in the order they are written.
+pub const FIELDS: &[&str] = &[
+    "pkgname", "pkgver", "pkgrel", "epoch", "pkgdesc", "url", "license", "install", "changelog",
+    "source", "validpgpkeys", "noextract",
+    "md5sums", "sha1sums", "sha224sums", "sha256sums", "sha384sums", "sha512sums", "b2sums",
+    "groups", "arch", "backup", "depends", "makedepends", "checkdepends", "optdepends",
+    "conflicts", "provides", "replaces", "options",
+];
+
+/// The PKGBUILD variables makepkg can't do without.
+pub const MANDATORY_FIELDS: &[&str] = &["pkgname", "pkgver", "pkgrel", "arch"];
+
+/// Parse a comma separated list of PKGBUILD variables.
+pub fn parse_fields(list: &str) -> Result<Vec<String>, String> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|field| !field.is_empty())
+        .map(|field| match FIELDS.contains(&field) {
+            true => Ok(field.to_string()),
+            false => Err(format!("unknown field `{}`", field)),
+        })
+        .collect()
 }
 
 /// see `man PKGBUILD`
@@ -107,6 +427,8 @@ pub struct ArchConfig {
     pub source: Vec<String>,
     /// An array of PGP fingerprints.
     pub validpgpkeys: Vec<String>,
+    /// The owners of the `validpgpkeys`, by fingerprint.
+    pub pgpkeys: BTreeMap<String, String>,
     /// An array of file names corresponding to those from the source array.
     pub noextract: Vec<String>,
     /// This array contains an MD5 hash for every source file specified in the source array (in the same order).
@@ -114,11 +436,15 @@ pub struct ArchConfig {
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha1sums: Vec<String>,
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
+    pub sha224sums: Vec<String>,
+    /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha256sums: Vec<String>,
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha384sums: Vec<String>,
     /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
     pub sha512sums: Vec<String>,
+    /// Alternative integrity checks that makepkg supports; these all behave similar to the md5sums option described above.
+    pub b2sums: Vec<String>,
     /// An array of symbolic names that represent groups of packages,
     /// allowing you to install multiple packages by requesting a single target.
     pub groups: Vec<String>,
@@ -127,6 +453,8 @@ pub struct ArchConfig {
     /// An array of file names, without preceding slashes,
     /// that should be backed up if the package is removed or upgraded.
     pub backup: Vec<String>,
+    /// The `depends` written with the `lib32-` prefix for multilib, empty unless `multilib` is set.
+    pub multilib_libs: Vec<String>,
     /// An array of packages this package depends on to run.
     pub depends: Vec<String>,
     /// An array of packages this package depends on to build but are not needed at runtime.
@@ -136,6 +464,8 @@ pub struct ArchConfig {
     /// An array of packages (and accompanying reasons) that are not essential for base functionality,
     /// but may be necessary to make full use of the contents of this package.
     pub optdepends: Vec<String>,
+    /// Write `optdepends` one entry per line, with the reasons aligned.
+    pub wrap_optdepends: bool,
     /// An array of packages that will conflict with this package.
     pub conflicts: Vec<String>,
     /// An array of "virtual provisions" this package provides.
@@ -144,30 +474,170 @@ pub struct ArchConfig {
     pub replaces: Vec<String>,
     /// This array allows you to override some of makepkg’s default behavior when building packages.
     pub options: Vec<String>,
+    /// Append a `# vim:set ts=<indent> sw=<indent> et:` modeline at the end of the PKGBUILD.
+    pub modeline: bool,
+    /// Warn when `pkgdesc` is longer than this many characters.
+    pub pkgdesc_max_length: usize,
+    /// This is a VCS package, its pkgver is computed by `pkgver()` from the git history.
+    pub vcs: bool,
+    /// Name of the embedded template providing the build functions.
+    pub template_name: String,
+    /// Name of the crate on crates.io.
+    pub crate_name: String,
+    /// Version of the crate on crates.io.
+    pub crate_version: String,
+    /// Append the template's build functions after the variables.
+    pub include_template: bool,
+    /// One level of indentation inside the build functions.
+    pub indent: String,
+    /// Line ending of every PKGBUILD line.
+    pub newline: String,
+    /// End the PKGBUILD and .SRCINFO with exactly one line ending, otherwise with none.
+    pub final_newline: bool,
+    /// Vendor the crate dependencies in `prepare()` and build offline.
+    pub vendor: bool,
+    /// Features the build enables.
+    pub features: Vec<String>,
+    /// Build without the `default` feature.
+    pub no_default_features: bool,
+    /// Apply the `.patch` and `.diff` sources in `prepare()`.
+    pub auto_apply_patches: bool,
+    /// Fetch the crate dependencies in `prepare()`.
+    pub fetch_in_prepare: bool,
+    /// Require a `Cargo.lock` and build with `--locked`.
+    pub locked: bool,
+    /// Directory holding the Cargo.toml.
+    pub manifest_dir: PathBuf,
+    /// `--target-dir`, cargo's target directory relative to the crate, passed on to cargo.
+    pub cargo_target_dir: Option<String>,
+    /// cargo's target directory relative to the crate without `--target-dir`, see `pkgbuild_target_dir`.
+    pub default_target_dir: String,
+    /// Directory the PKGBUILD is written into.
+    pub output_dir: PathBuf,
+    /// File name of the PKGBUILD.
+    pub pkgbuild_name: String,
+    /// File name of the .SRCINFO.
+    pub srcinfo_name: String,
+    /// Fields to emit besides the mandatory ones, all of them if empty.
+    pub only: Vec<String>,
+    /// Cross-compile for this target triple.
+    pub target: Option<String>,
+    /// Binaries `package()` installs.
+    pub bins: Vec<String>,
+    /// Directory under `$pkgdir` the binaries are installed into.
+    pub bin_dir: String,
+    /// Octal permissions of the installed binaries.
+    pub bin_mode: String,
+    /// Other files `package()` installs.
+    pub install_files: Vec<CargoInstallFile>,
+    /// Whether `install` is generated, running `systemd-sysusers` and `systemd-tmpfiles` on the configs below.
+    pub install_script: bool,
+    /// The sysusers.d config, see `CargoArch::sysusers`.
+    pub sysusers: Option<String>,
+    /// The tmpfiles.d config, see `CargoArch::tmpfiles`.
+    pub tmpfiles: Option<String>,
+    /// Name of the library target, if the crate has one.
+    pub lib_name: Option<String>,
+    /// Add the soname of the built cdylib to `provides`.
+    pub cdylib_provides: bool,
+    /// Other workspace members installing a binary of the same name conflict with this package.
+    pub detect_bin_conflicts: bool,
+    /// Directory under `$srcdir` holding the crate, the PKGBUILD's own directory when unset.
+    pub build_dir: Option<String>,
+    /// Fields not to emit, mandatory ones are always written.
+    pub exclude: Vec<String>,
+    /// Which crate dependencies `depends` is derived from.
+    pub depends_from: DependsFrom,
+    /// Names of the crates `depends` is derived from.
+    pub crate_dependencies: Vec<String>,
+    /// Names of the crates in `[build-dependencies]`, empty without a build script.
+    pub build_dependencies: Vec<String>,
+    /// The `links` key of a crate with a build script.
+    pub links: Option<String>,
+    /// The manifest's crate to pacman package mapping.
+    pub depmap: BTreeMap<String, String>,
+    /// Problems found while resolving the config.
+    pub diagnostics: Diagnostics,
+    /// Where each PKGBUILD field's value comes from, ordered by field name.
+    provenance: BTreeMap<&'static str, FieldSource>,
 }
 
 impl ArchConfig {
-    pub fn new(manifest_path: Option<&str>) -> ArchConfig {
-        let mut content = String::new();
-        let path = format!(
-            "{}/Cargo.toml",
-            match manifest_path {
-                Some(val) => val.to_string(),
-                None => match std::env::var("CARGO_MANIFEST_DIR") {
-                    Ok(val) => val,
-                    Err(_) => ".".to_string(),
-                }
-            }
-        );
-        let mut path = File::open(path.as_str()).unwrap();
-        path.read_to_string(&mut content)
-            .expect("cargo-arch: invalid or missing Cargo.toml options");
-        toml::from_str::<Cargo>(&content)
-            .expect("cargo-arch: could not decode manifest")
-            .to_config()
+    pub fn new(manifest_path: Option<&str>, metadata_section: Option<&str>) -> ArchConfig {
+        Cargo::from_dir(&manifest_dir(manifest_path), metadata_section).to_config()
+    }
+
+    /// The config of a manifest given as a string, see `Cargo::from_manifest_str`
+    pub fn from_manifest_str(content: &str, metadata_section: Option<&str>) -> ArchConfig {
+        Cargo::from_manifest_str(content, metadata_section).to_config()
+    }
+
+    /// Where each PKGBUILD field's value comes from, by field name
+    pub fn provenance(&self) -> &BTreeMap<&'static str, FieldSource> {
+        &self.provenance
+    }
+
+    /// Record that `field` was changed after the config was resolved, by a command line option
+    pub fn set_provenance(&mut self, field: &'static str, source: FieldSource) {
+        self.provenance.insert(field, source);
+    }
+
+    /// The `<algo>sums` array named `array`, e.g. `sha256sums`
+    pub fn checksums_mut(&mut self, array: &str) -> Option<&mut Vec<String>> {
+        match array {
+            "md5sums" => Some(&mut self.md5sums),
+            "sha1sums" => Some(&mut self.sha1sums),
+            "sha224sums" => Some(&mut self.sha224sums),
+            "sha256sums" => Some(&mut self.sha256sums),
+            "sha384sums" => Some(&mut self.sha384sums),
+            "sha512sums" => Some(&mut self.sha512sums),
+            "b2sums" => Some(&mut self.b2sums),
+            _ => None,
+        }
+    }
+
+    /// The separate package makepkg splits the debug symbols into, when `options` enables `debug`.
+    /// It's not in the PKGBUILD nor in `makepkg --printsrcinfo`'s output.
+    pub fn debug_pkgname(&self) -> Option<String> {
+        let debug = self.options.iter()
+                                .rev()
+                                .find(|option| *option == "debug" || *option == "!debug")
+                                .map(|option| option == "debug")
+                                .unwrap_or(false);
+        if debug {
+            Some(format!("{}-debug", self.pkgname))
+        } else {
+            None
+        }
+    }
+
+    /// Where the PKGBUILD is written
+    pub fn pkgbuild_path(&self) -> PathBuf {
+        self.output_dir.join(&self.pkgbuild_name)
     }
 
-    pub fn generate_pkgbuild(&self) {
+    /// Where the .SRCINFO is written
+    pub fn srcinfo_path(&self) -> PathBuf {
+        self.output_dir.join(&self.srcinfo_name)
+    }
+
+    /// Where the `install` script is written, when it is generated
+    pub fn install_script_path(&self) -> Option<PathBuf> {
+        self.install_script.then(|| self.output_dir.join(&self.install))
+    }
+
+    /// Rename the PKGBUILD, the .SRCINFO follows it unless named explicitly:
+    /// `PKGBUILD.generated` goes with `.SRCINFO.generated`, `foo` with `foo.SRCINFO`.
+    pub fn set_pkgbuild_name(&mut self, pkgbuild_name: &str, srcinfo_name: Option<&str>) {
+        self.pkgbuild_name = pkgbuild_name.to_string();
+        self.srcinfo_name = match (srcinfo_name, pkgbuild_name.strip_prefix("PKGBUILD")) {
+            (Some(srcinfo_name), _) => srcinfo_name.to_string(),
+            (None, Some(suffix)) => format!(".SRCINFO{}", suffix),
+            (None, None) => format!("{}.SRCINFO", pkgbuild_name),
+        };
+    }
+
+    pub fn render_pkgbuild(&self) -> String {
         let mut buffer = String::new();
 
         macro_rules! add_data {
@@ -176,150 +646,1720 @@ impl ArchConfig {
             }
         }
 
-        fn quote_data(data: &Vec<String>) -> String {
+        fn quote_data(data: &[String]) -> String {
             let mut buffer = String::new();
 
-            if data.len() == 0 {
+            if data.is_empty() {
                 return buffer;
             }
 
-            buffer.push_str("\"");
+            buffer.push('"');
             buffer.push_str(data[0].as_str());
-            buffer.push_str("\"");
+            buffer.push('"');
 
             for i in data.iter().skip(1) {
                 buffer.push_str(", \"");
                 buffer.push_str(i);
-                buffer.push_str("\"");
+                buffer.push('"');
             }
 
             buffer
         }
 
+        add_data!("{}\n", generated_by());
         for i in &self.maintainers {
             add_data!("# Maintainer: {}\n", i);
         }
-        buffer.push_str("\n");
-
-        add_data!("pkgname={}\n", self.pkgname);
-        add_data!("pkgver={}\n", self.pkgver.replace("-","_"));
-        add_data!("pkgrel={}\n", self.pkgrel);
-        add_data!("epoch={}\n", self.epoch);
-        add_data!("pkgdesc=\"{}\"\n", self.pkgdesc);
-        add_data!("url=\"{}\"\n", self.url);
-        add_data!("license=({})\n", quote_data(&self.license));
-        add_data!("install=\"{}\"\n", self.install);
-        add_data!("changelog=\"{}\"\n", self.changelog);
-        add_data!("source=({})\n", quote_data(&self.source));
-        add_data!("validpgpkeys=({})\n", quote_data(&self.validpgpkeys));
-        add_data!("noextract=({})\n", quote_data(&self.noextract));
-        add_data!("md5sums=({})\n", quote_data(&self.md5sums));
-        add_data!("sha1sums=({})\n", quote_data(&self.sha1sums));
-        add_data!("sha256sums=({})\n", quote_data(&self.sha256sums));
-        add_data!("sha384sums=({})\n", quote_data(&self.sha384sums));
-        add_data!("sha512sums=({})\n", quote_data(&self.sha512sums));
-        add_data!("groups=({})\n", quote_data(&self.groups));
-        add_data!("arch=({})\n", quote_data(&self.arch));
-        add_data!("backup=({})\n", quote_data(&self.backup));
-        add_data!("depends=({})\n", quote_data(&self.depends));
-        add_data!("makedepends=({})\n", quote_data(&self.makedepends));
-        add_data!("checkdepends=({})\n", quote_data(&self.checkdepends));
-        add_data!("optdepends=({})\n", quote_data(&self.optdepends));
-        add_data!("conflicts=({})\n", quote_data(&self.conflicts));
-        add_data!("provides=({})\n", quote_data(&self.provides));
-        add_data!("replaces=({})\n", quote_data(&self.replaces));
-        add_data!("options=({})\n", quote_data(&self.options));
-
-        buffer.push_str("\n");
-        buffer.push_str(include_str!("PKGBUILD-TEMPLATE"));
-
-        let mut file = File::create("PKGBUILD").unwrap();
-        write!(file, "{}", buffer).unwrap();
+        buffer.push('\n');
+
+        let source = signature_comments(&self.source, &self.validpgpkeys)
+                     + &format!("source=({})\n", quote_data(&self.source));
+
+        let options = match self.debug_pkgname() {
+            Some(debug_pkgname) => format!("# makepkg also builds {} from the debug symbols\n", debug_pkgname),
+            None => String::new(),
+        } + &format!("options=({})\n", quote_data(&self.options));
+
+        let fields = vec![
+            ("pkgname", format!("pkgname={}\n", self.pkgname)),
+            ("pkgver", format!("pkgver={}\n", sanitize_pkgver(&self.pkgver))),
+            ("pkgrel", format!("pkgrel={}\n", self.pkgrel)),
+            ("epoch", format!("epoch={}\n", self.epoch)),
+            ("pkgdesc", format!("pkgdesc=\"{}\"\n", self.pkgdesc)),
+            ("url", format!("url=\"{}\"\n", self.url)),
+            ("license", format!("license=({})\n", quote_data(&self.license))),
+            ("install", format!("install=\"{}\"\n", self.install)),
+            ("changelog", format!("changelog=\"{}\"\n", self.changelog)),
+            ("source", source),
+            ("validpgpkeys", self.render_validpgpkeys(quote_data(&self.validpgpkeys))),
+            ("noextract", format!("noextract=({})\n", quote_data(&self.noextract))),
+            ("md5sums", format!("md5sums=({})\n", quote_data(&self.md5sums))),
+            ("sha1sums", format!("sha1sums=({})\n", quote_data(&self.sha1sums))),
+            ("sha224sums", format!("sha224sums=({})\n", quote_data(&self.sha224sums))),
+            ("sha256sums", format!("sha256sums=({})\n", quote_data(&self.sha256sums))),
+            ("sha384sums", format!("sha384sums=({})\n", quote_data(&self.sha384sums))),
+            ("sha512sums", format!("sha512sums=({})\n", quote_data(&self.sha512sums))),
+            ("b2sums", format!("b2sums=({})\n", quote_data(&self.b2sums))),
+            ("groups", format!("groups=({})\n", quote_data(&self.groups))),
+            ("arch", format!("arch=({})\n", quote_data(&self.arch))),
+            ("backup", format!("backup=({})\n", quote_data(&self.backup))),
+            ("depends", format!("depends=({})\n", quote_data(&self.multilib_depends()))),
+            ("makedepends", format!("makedepends=({})\n", quote_data(&self.makedepends))),
+            ("checkdepends", format!("checkdepends=({})\n", quote_data(&self.checkdepends))),
+            ("optdepends", match self.wrap_optdepends && !self.optdepends.is_empty() {
+                true => self.render_wrapped_optdepends(),
+                false => format!("optdepends=({})\n", quote_data(&self.optdepends)),
+            }),
+            ("conflicts", format!("conflicts=({})\n", quote_data(&self.conflicts))),
+            ("provides", format!("provides=({})\n", quote_data(&self.provides))),
+            ("replaces", format!("replaces=({})\n", quote_data(&self.replaces))),
+            ("options", options),
+        ];
+        debug_assert!(fields.iter().map(|(name, _)| *name).eq(FIELDS.iter().copied()));
+
+        for (name, line) in fields {
+            if self.emits(name) {
+                buffer.push_str(&line);
+            }
+        }
+
+        if self.include_template {
+            buffer.push('\n');
+            buffer.push_str(&self.render_prepare());
+            buffer.push_str(&self.render_template());
+        }
+
+        if self.modeline {
+            buffer.push_str(&format!("\n# vim:set ts={0} sw={0} et:\n", self.indent.len()));
+        }
+
+        final_newline(&self.newlines(&buffer), self.final_newline.then_some(self.newline.as_str()))
+    }
+
+    /// `validpgpkeys=`, one key per line with its owner when `pgpkeys` names any, else `compact` on one line
+    fn render_validpgpkeys(&self, compact: String) -> String {
+        let normalize = |fingerprint: &str| fingerprint.replace(' ', "").to_uppercase();
+        let owner = |key: &str| self.pgpkeys.iter()
+                                            .find(|(fingerprint, _)| normalize(fingerprint) == normalize(key))
+                                            .map(|(_, owner)| owner);
+
+        if !self.validpgpkeys.iter().any(|key| owner(key).is_some()) {
+            return format!("validpgpkeys=({})\n", compact);
+        }
+
+        let mut buffer = String::from("validpgpkeys=(\n");
+        for key in &self.validpgpkeys {
+            buffer.push_str(&format!("{}\"{}\"", self.indent(1), key));
+            if let Some(owner) = owner(key) {
+                buffer.push_str(&format!("  # {}", owner));
+            }
+            buffer.push('\n');
+        }
+        buffer.push_str(")\n");
+        buffer
+    }
+
+    /// `depends` with the `multilib_libs` prefixed by `lib32-`, only for packages built for `x86_64` alone
+    fn multilib_depends(&self) -> Vec<String> {
+        if self.multilib_libs.is_empty() || self.arch != ["x86_64"] {
+            return self.depends.clone();
+        }
+        self.depends.iter()
+                    .map(|depend| match self.multilib_libs.iter().any(|lib| lib == relation_name(depend)) {
+                        true => format!("lib32-{}", depend),
+                        false => depend.clone(),
+                    })
+                    .collect()
+    }
+
+    /// `optdepends=`, one entry per line with the reasons lined up by padding after the `:`,
+    /// inside the quotes, like hand-formatted PKGBUILDs
+    fn render_wrapped_optdepends(&self) -> String {
+        let width = self.optdepends.iter()
+                                   .filter_map(|entry| entry.split_once(':'))
+                                   .map(|(name, _)| name.trim_end().chars().count())
+                                   .max()
+                                   .unwrap_or(0);
+
+        let mut buffer = String::from("optdepends=(\n");
+        for entry in &self.optdepends {
+            let entry = match entry.split_once(':') {
+                Some((name, reason)) => format!("{:width$} {}", format!("{}:", name.trim_end()), reason.trim_start(),
+                                                width = width + 1),
+                None => entry.clone(),
+            };
+            buffer.push_str(&format!("{}\"{}\"\n", self.indent(1), entry));
+        }
+        buffer.push_str(")\n");
+        buffer
+    }
+
+    /// Whether `field` is written to the PKGBUILD
+    fn emits(&self, field: &str) -> bool {
+        if MANDATORY_FIELDS.contains(&field) {
+            return true;
+        }
+        (self.only.is_empty() || self.only.iter().any(|only| only == field))
+            && !self.exclude.iter().any(|exclude| exclude == field)
+    }
+
+    /// `level` levels of indentation
+    fn indent(&self, level: usize) -> String {
+        self.indent.repeat(level)
+    }
+
+    /// Re-indent text written with four spaces per level
+    fn reindent(&self, text: &str) -> String {
+        text.split_inclusive('\n')
+            .map(|line| {
+                let content = line.trim_start_matches(' ');
+                let level = (line.len() - content.len()) / 4;
+                self.indent(level) + content
+            })
+            .collect()
     }
+
+    /// Turn the `\n` every renderer emits into the configured line ending
+    fn newlines(&self, text: &str) -> String {
+        if self.newline == "\n" {
+            text.to_string()
+        } else {
+            text.replace('\n', &self.newline)
+        }
+    }
+
+    /// The generated `prepare()` function, empty when there is nothing to prepare
+    fn render_prepare(&self) -> String {
+        let mut lines = vec![];
+
+        if self.auto_apply_patches {
+            for source in &self.source {
+                let filename = source_filename(source);
+                if filename.ends_with(".patch") || filename.ends_with(".diff") {
+                    lines.push(format!("patch -Np1 -i \"$srcdir/{}\"", filename));
+                }
+            }
+        }
+
+        if self.fetch_in_prepare {
+            match &self.target {
+                Some(target) => lines.push(format!("cargo fetch --locked --target {}", target)),
+                None => lines.push("cargo fetch --locked".to_string()),
+            }
+        }
+
+        if self.vendor {
+            lines.push("mkdir -p .cargo".to_string());
+            lines.push("cargo vendor --locked > .cargo/config.toml".to_string());
+        }
+
+        if lines.is_empty() {
+            return String::new();
+        }
+
+        let mut buffer = String::from("prepare() {\n");
+        for line in std::iter::once(self.cd()).chain(lines) {
+            buffer.push_str(&self.indent(1));
+            buffer.push_str(&line);
+            buffer.push('\n');
+        }
+        buffer.push_str("}\n\n");
+        buffer
+    }
+
+    /// Where cargo puts the release artifacts relative to the crate, for the PKGBUILD:
+    /// the build environment's `CARGO_TARGET_DIR` wins, like it does for cargo
+    pub fn target_dir(&self) -> String {
+        let target_dir = self.cargo_target_dir.as_deref().unwrap_or(&self.default_target_dir);
+        release_dir(&format!("${{CARGO_TARGET_DIR:-{}}}", target_dir.trim_end_matches('/')), self.target.as_deref())
+    }
+
+    /// Where a build on this machine put the release artifacts, relative to the crate unless absolute
+    pub fn host_target_dir(&self) -> String {
+        let target_dir = self.cargo_target_dir.clone()
+                                              .or_else(|| cargo_target_dir(&self.manifest_dir))
+                                              .unwrap_or_else(|| self.default_target_dir.clone());
+        release_dir(target_dir.trim_end_matches('/'), self.target.as_deref())
+    }
+
+    /// Change into the crate from `$srcdir`
+    fn cd(&self) -> String {
+        match &self.build_dir {
+            Some(build_dir) => format!("cd \"$srcdir/{}\"", build_dir),
+            None => "cd ..".to_string(),
+        }
+    }
+
+    /// The template's build functions with the placeholders filled in
+    fn render_template(&self) -> String {
+        let mut cargo_flags = String::new();
+
+        if self.locked {
+            cargo_flags.push_str(" --locked");
+        }
+        if self.vendor {
+            cargo_flags.push_str(" --offline");
+        }
+        if let Some(target) = &self.target {
+            cargo_flags.push_str(&format!(" --target {}", target));
+        }
+        if let Some(target_dir) = &self.cargo_target_dir {
+            cargo_flags.push_str(&format!(" --target-dir \"${{CARGO_TARGET_DIR:-{}}}\"", target_dir));
+        }
+        if self.no_default_features {
+            cargo_flags.push_str(" --no-default-features");
+        }
+        if !self.features.is_empty() {
+            cargo_flags.push_str(&format!(" --features {}", self.features.join(",")));
+        }
+        let target_dir = self.target_dir();
+
+        let bins = self.bins.iter()
+                            .map(|bin| format!("\"{}/{}\"", target_dir, bin))
+                            .collect::<Vec<String>>()
+                            .join(" ");
+
+        let template = template(&self.template_name).expect("unknown template")
+                                                    .replace("{{cargo_flags}}", &cargo_flags)
+                                                    .replace("{{bin_mode}}", &self.bin_mode)
+                                                    .replace("{{bin_dir}}", &self.bin_dir)
+                                                    .replace("{{bins}}", &bins)
+                                                    .replace("{{target_dir}}", &target_dir)
+                                                    .replace("{{crate_name}}", &self.crate_name)
+                                                    .replace("{{crate_version}}", &self.crate_version);
+
+        let template = self.add_install_files(template);
+
+        // the templates expect the crate in the PKGBUILD's directory, otherwise every function moves into it
+        let template = match self.build_dir {
+            Some(_) => template.split_inclusive('\n')
+                               .filter(|line| line.trim() != "cd ..")
+                               .map(|line| match line.trim_end().ends_with("() {") {
+                                   true => format!("{}    {}\n", line, self.cd()),
+                                   false => line.to_string(),
+                               })
+                               .collect(),
+            None => template,
+        };
+        self.reindent(&template)
+    }
+
+    /// `install_files` installed at the end of the template's `package()`
+    fn add_install_files(&self, template: String) -> String {
+        let mut buffer = String::new();
+        let mut in_package = false;
+
+        for line in template.split_inclusive('\n') {
+            if line.starts_with("package() {") {
+                in_package = true;
+            } else if in_package && line.trim_end() == "}" {
+                for install_file in &self.install_files {
+                    buffer.push_str(&format!("    install -Dm{} \"{}\" \"$pkgdir/{}\"\n",
+                                             install_file.mode.as_deref().unwrap_or("644"),
+                                             install_file.src,
+                                             install_file.dest));
+                }
+                in_package = false;
+            }
+            buffer.push_str(line);
+        }
+
+        buffer
+    }
+
+    /// Switch the template, `cargo-install` needs cargo to build
+    pub fn set_template_name(&mut self, template_name: &str) {
+        self.template_name = template_name.to_string();
+        if template_name == "cargo-install"
+           && !self.makedepends.iter().any(|dep| relation_name(dep) == "cargo" || relation_name(dep) == "rust") {
+            self.makedepends.push("cargo".to_string());
+            self.set_provenance("makedepends", FieldSource::CommandLine);
+        }
+    }
+
+    /// `--pkgrel`, overriding every other source of `pkgrel`
+    pub fn set_pkgrel(&mut self, pkgrel: &str) {
+        self.pkgrel = pkgrel.to_string();
+        self.set_provenance("pkgrel", FieldSource::CommandLine);
+    }
+
+    /// Cross-compile for `triple`, building for its architecture only
+    pub fn set_target(&mut self, triple: &str) -> Result<(), String> {
+        self.arch = vec![target_arch(triple)?.to_string()];
+        self.target = Some(triple.to_string());
+        self.set_provenance("arch", FieldSource::CommandLine);
+        Ok(())
+    }
+
+    pub fn generate_pkgbuild(&self) -> Result<(), String> {
+        let path = self.pkgbuild_path();
+        fs::write(&path, self.render_pkgbuild()).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        log::info!("wrote {}", path.display());
+        Ok(())
+    }
+
+    /// The generated `install` script, applying the sysusers.d and tmpfiles.d configs
+    /// when the package is installed or upgraded
+    pub fn render_install_script(&self) -> String {
+        let mut buffer = String::from("post_install() {\n");
+        if self.sysusers.is_some() {
+            buffer.push_str(&format!("    systemd-sysusers {}.conf\n", self.pkgname));
+        }
+        if self.tmpfiles.is_some() {
+            buffer.push_str(&format!("    systemd-tmpfiles --create {}.conf\n", self.pkgname));
+        }
+        buffer.push_str("}\n\npost_upgrade() {\n    post_install\n}\n");
+        self.newlines(&self.reindent(&buffer))
+    }
+
+    /// Write the `install` script next to the PKGBUILD when it is generated
+    pub fn generate_install_script(&self) -> Result<(), String> {
+        let path = match self.install_script_path() {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        fs::write(&path, self.render_install_script()).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+        log::info!("wrote {}", path.display());
+        Ok(())
+    }
+}
+
+/// The `arch` values `CARGO_ARCH_ARCH` accepts, the ones `target_arch` maps to and `any`
+pub const ARCHES: &[&str] = &["any", "x86_64", "i686", "aarch64", "armv7h", "armv6h", "riscv64", "powerpc64le", "loong64"];
+
+/// The first line of every generated PKGBUILD, telling it apart from a hand written one
+pub const GENERATED_BY: &str = "# Generated by cargo-arch";
+
+/// `GENERATED_BY` with the version of cargo-arch
+pub fn generated_by() -> String {
+    format!("{} {}", GENERATED_BY, env!("CARGO_PKG_VERSION"))
+}
+
+/// The Arch Linux `arch` of a Rust target triple, `aarch64-unknown-linux-gnu` is `aarch64`.
+pub fn target_arch(triple: &str) -> Result<&'static str, String> {
+    let mut components = triple.split('-');
+    let cpu = components.next().unwrap_or_default();
+    if !components.any(|component| component == "linux") {
+        return Err(format!("`{}` is not a Linux target", triple));
+    }
+
+    match cpu {
+        "x86_64" => Ok("x86_64"),
+        "i586" | "i686" => Ok("i686"),
+        "aarch64" => Ok("aarch64"),
+        "armv7" => Ok("armv7h"),
+        "arm" if triple.ends_with("hf") => Ok("armv6h"),
+        "riscv64gc" => Ok("riscv64"),
+        "powerpc64le" => Ok("powerpc64le"),
+        "loongarch64" => Ok("loong64"),
+        _ => Err(format!("no Arch Linux architecture for `{}`", triple)),
+    }
+}
+
+/// The file name makepkg saves a `source` entry as,
+/// either the `name::` prefix or the last path segment.
+pub fn source_filename(source: &str) -> &str {
+    match source.split_once("::") {
+        Some((name, _)) => name,
+        None => source.trim_end_matches('/').rsplit('/').next().unwrap_or(source),
+    }
+}
+
+/// `source` with the `$pkgname`/`${pkgname}` and `$pkgver`/`${pkgver}` bash expands in the PKGBUILD
+/// replaced by their values, for finding the file makepkg would fetch
+pub fn expand_source(source: &str, pkgname: &str, pkgver: &str) -> String {
+    let pkgver = sanitize_pkgver(pkgver);
+    source.replace("${pkgname}", pkgname)
+          .replace("${pkgver}", &pkgver)
+          .replace("$pkgname", pkgname)
+          .replace("$pkgver", &pkgver)
+}
+
+/// `text` with its trailing line endings replaced by `newline`, or stripped when there is none
+pub fn final_newline(text: &str, newline: Option<&str>) -> String {
+    text.trim_end_matches(['\n', '\r']).to_string() + newline.unwrap_or("")
+}
+
+/// Whether makepkg downloads the source instead of reading it next to the PKGBUILD
+pub fn is_remote(source: &str) -> bool {
+    let url = source.split_once("::").map(|(_, url)| url).unwrap_or(source);
+    url.contains("://")
+}
+
+/// The `release` directory in `target_dir`, under the `target` triple when cross-compiling
+fn release_dir(target_dir: &str, target: Option<&str>) -> String {
+    match target {
+        Some(target) => format!("{}/{}/release", target_dir, target),
+        None => format!("{}/release", target_dir),
+    }
+}
+
+/// VCS sources are checked out, not verified by checksum
+pub fn is_vcs(source: &str) -> bool {
+    let url = source.split_once("::").map(|(_, url)| url).unwrap_or(source);
+    ["git+", "hg+", "svn+", "bzr+", "fossil+"].iter().any(|scheme| url.starts_with(scheme))
+}
+
+/// Comments pairing each `.sig`/`.asc` source with the file it signs and the keys verifying it
+fn signature_comments(source: &[String], validpgpkeys: &[String]) -> String {
+    let mut buffer = String::new();
+
+    if validpgpkeys.is_empty() {
+        return buffer;
+    }
+
+    let filenames = source.iter().map(|s| source_filename(s)).collect::<Vec<&str>>();
+
+    for signature in &filenames {
+        let signed = signature.strip_suffix(".sig").or_else(|| signature.strip_suffix(".asc"));
+        if let Some(signed) = signed.filter(|signed| filenames.contains(signed)) {
+            buffer.push_str(&format!("# {} is verified by {} with key(s): {}\n",
+                                     signed, signature, validpgpkeys.join(", ")));
+        }
+    }
+
+    buffer
+}
+
+/// The package name of a relation like `foo>=1.0` or `foo: reason`
+pub fn relation_name(relation: &str) -> &str {
+    relation.split(|c| "<>=:".contains(c)).next().unwrap_or(relation).trim()
+}
+
+/// Add `package` to a relation array unless it's already listed or is the package itself
+pub fn add_package(array: &mut Vec<String>, package: &str, pkgname: &str) {
+    if package != pkgname && !array.iter().any(|entry| relation_name(entry) == package) {
+        array.push(package.to_string());
+    }
+}
+
+/// pkgname without its trailing version suffix, `foo` for `foo1.0` or `foo-2`
+fn versioned_base(pkgname: &str) -> &str {
+    let base = pkgname.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.')
+                      .trim_end_matches(['-', '_']);
+    if base.is_empty() { pkgname } else { base }
+}
+
+/// The license identifiers of a Cargo `license`, an SPDX expression or the old `/` separated list
+fn license_tokens(license: &str) -> Vec<&str> {
+    license.split(|c: char| c == '/' || c == '(' || c == ')' || c.is_whitespace())
+           .filter(|token| !token.is_empty() && !["OR", "AND", "WITH"].contains(token))
+           .collect()
+}
+
+/// Whether two license names mean the same license, a family name like `GPL` matches `GPL-3.0-or-later`
+fn same_license(a: &str, b: &str) -> bool {
+    fn normalize(license: &str) -> String {
+        license.trim()
+               .trim_end_matches('+')
+               .trim_end_matches("-only")
+               .trim_end_matches("-or-later")
+               .to_lowercase()
+    }
+    let (a, b) = (normalize(a), normalize(b));
+    a == b || b.starts_with(&format!("{}-", a)) || a.starts_with(&format!("{}-", b))
+}
+
+/// Whether `mode` is octal permissions like `755` or `0644`
+fn is_octal_mode(mode: &str) -> bool {
+    (3..=4).contains(&mode.len()) && mode.chars().all(|c| ('0'..='7').contains(&c))
+}
+
+/// `-` is not allowed in pkgver, turn it into `_`.
+/// The `+` of SemVer build metadata is allowed and kept, `1.2.3-rc.1+build.5` is `1.2.3_rc.1+build.5`.
+pub fn sanitize_pkgver(pkgver: &str) -> String {
+    pkgver.replace('-', "_")
 }
 
 impl ToPackageConfig<ArchConfig> for Cargo {
     fn to_config(&self) -> ArchConfig {
-        let cargo_metadata_default = CargoMetadata::default();
-        let cargo_arch_default = CargoArch::default();
-        let arch_config = self.package.metadata.as_ref().unwrap_or(&cargo_metadata_default).arch.as_ref().unwrap_or(&cargo_arch_default);
+        let mut diagnostics = Diagnostics::new();
+        let metadata_section = self.metadata_section.as_deref().unwrap_or("arch");
+        let arch_config = &self.package.metadata.as_ref()
+                                                .and_then(|metadata| metadata.arch(metadata_section))
+                                                .unwrap_or_default();
+        for (key, suggestion) in self.package.metadata.as_ref()
+                                                      .map(|metadata| metadata.unknown_keys(metadata_section))
+                                                      .unwrap_or_default() {
+            diagnostics.error(
+                "metadata", "unknown-key",
+                match suggestion {
+                    Some(field) => format!("unknown key `{}` in [package.metadata.{}], did you mean `{}`?",
+                                           key, metadata_section, field),
+                    None => format!("unknown key `{}` in [package.metadata.{}]", key, metadata_section),
+                });
+        }
+        for (alias, field) in self.package.metadata.as_ref()
+                                                   .map(|metadata| metadata.aliases(metadata_section))
+                                                   .unwrap_or_default() {
+            diagnostics.warning(
+                field, "alias-key",
+                format!("`{}` is an alias of `{}`, rename it", alias, field));
+        }
 
-        let maintainers = arch_config.maintainers.as_ref().unwrap_or(&self.package.authors).clone();
-        let pkgname = arch_config.pkgname.as_ref().unwrap_or(&self.package.name).clone();
+        let maintainers = arch_config.maintainers.as_ref()
+                                     .unwrap_or(&self.package.authors)
+                                     .iter()
+                                     .map(CargoAuthor::normalize)
+                                     .filter(|maintainer| !maintainer.is_empty())
+                                     .collect::<Vec<String>>();
+        let pkgname = match arch_config.pkgname.as_ref() {
+            Some(pkgname) => pkgname.clone(),
+            None if self.vcs => format!("{}-git", self.package.name),
+            None => self.package.name.clone(),
+        };
         let pkgver = arch_config.pkgver.as_ref().unwrap_or(&self.package.version).clone();
-        let pkgrel = arch_config.pkgrel.as_ref().unwrap_or(&"1".to_string()).clone();
+        let env_pkgrel = std::env::var("CARGO_ARCH_PKGREL").ok().filter(|pkgrel| !pkgrel.is_empty());
+        let pkgrel_source = match (&arch_config.pkgrel, &env_pkgrel, &arch_config.default_pkgrel) {
+            (Some(_), _, _) | (None, None, Some(_)) => FieldSource::Metadata,
+            (None, Some(_), _) => FieldSource::Environment,
+            (None, None, None) => FieldSource::Default,
+        };
+        let pkgrel = arch_config.pkgrel.clone()
+                                .or(env_pkgrel)
+                                .or_else(|| arch_config.default_pkgrel.clone())
+                                .unwrap_or_else(|| "1".to_string());
         let epoch = arch_config.epoch.as_ref().unwrap_or(&"0".to_string()).clone();
-        let pkgdesc = arch_config.pkgdesc.as_ref().unwrap_or(&self.package.description).clone();
+        let pkgdesc = match arch_config.pkgdesc.as_ref() {
+            Some(pkgdesc) => pkgdesc.clone(),
+            None if self.package.description.is_empty()
+                    && arch_config.pkgdesc_from_keywords.unwrap_or(false) => {
+                self.package.keywords.iter()
+                                     .chain(self.package.categories.iter())
+                                     .flatten()
+                                     .cloned()
+                                     .collect::<Vec<String>>()
+                                     .join(", ")
+            },
+            None => self.package.description.clone(),
+        };
+        let url_from = arch_config.url_from.as_ref().unwrap_or(
+            &vec!["homepage".to_string(), "repository".to_string()]
+        ).clone();
+        for field in &url_from {
+            if !["homepage", "repository", "documentation"].contains(&field.as_str()) {
+                diagnostics.error(
+                    "url_from", "unknown-url-field",
+                    format!("unknown field `{}`, expected one of `homepage`, `repository`, `documentation`",
+                            field));
+            }
+        }
         let url = arch_config.url.as_ref()
-                             .or(self.package.homepage.as_ref())
-                             .or(self.package.repository.as_ref())
+                             .or_else(|| url_from.iter()
+                                                 .filter_map(|field| match field.as_str() {
+                                                     "homepage" => self.package.homepage.as_ref(),
+                                                     "repository" => self.package.repository.as_ref(),
+                                                     "documentation" => self.package.documentation.as_ref(),
+                                                     _ => None,
+                                                 })
+                                                 .next())
                              .unwrap_or(&String::new())
                              .clone();
         let license = arch_config.license.as_ref().unwrap_or(
-            &self.package.license.split("/")
+            &self.package.license.split('/')
                                  .map(|s| s.to_string())
                                  .collect::<Vec<String>>()
         ).clone();
 
-        let install = arch_config.install.as_ref().unwrap_or(&String::new()).clone();
+        if arch_config.license.is_some() && !self.package.license.trim().is_empty() {
+            let cargo_licenses = license_tokens(&self.package.license);
+            let overlaps = license.iter().any(|arch_license| {
+                cargo_licenses.iter().any(|cargo_license| same_license(arch_license, cargo_license))
+            });
+            if !overlaps {
+                diagnostics.warning(
+                    "license", "license-mismatch",
+                    format!("({}) shares no license with Cargo's `{}`, double-check it",
+                            license.join(", "), self.package.license));
+            }
+        }
+
+        let mut install = arch_config.install.as_ref().unwrap_or(&String::new()).clone();
         let changelog = arch_config.changelog.as_ref().unwrap_or(&String::new()).clone();
-        let source = arch_config.source.as_ref().unwrap_or(&vec![]).clone();
+        let sources = arch_config.source.as_ref().unwrap_or(&vec![]).clone();
+        let source = sources.iter().map(CargoSource::entry).collect::<Vec<String>>();
         let validpgpkeys = arch_config.validpgpkeys.as_ref().unwrap_or(&vec![]).clone();
         let noextract = arch_config.noextract.as_ref().unwrap_or(&vec![]).clone();
-        let md5sums = arch_config.md5sums.as_ref().unwrap_or(&vec![]).clone();
-        let sha1sums = arch_config.sha1sums.as_ref().unwrap_or(&vec![]).clone();
-        let sha256sums = arch_config.sha256sums.as_ref().unwrap_or(&vec![]).clone();
-        let sha384sums = arch_config.sha384sums.as_ref().unwrap_or(&vec![]).clone();
-        let sha512sums = arch_config.sha512sums.as_ref().unwrap_or(&vec![]).clone();
+        let md5sums = merge_checksums(&sources, "md5sums", arch_config.md5sums.as_ref());
+        let sha1sums = merge_checksums(&sources, "sha1sums", arch_config.sha1sums.as_ref());
+        let sha224sums = merge_checksums(&sources, "sha224sums", arch_config.sha224sums.as_ref());
+        let sha256sums = merge_checksums(&sources, "sha256sums", arch_config.sha256sums.as_ref());
+        let sha384sums = merge_checksums(&sources, "sha384sums", arch_config.sha384sums.as_ref());
+        let sha512sums = merge_checksums(&sources, "sha512sums", arch_config.sha512sums.as_ref());
+        let b2sums = merge_checksums(&sources, "b2sums", arch_config.b2sums.as_ref());
         let groups = arch_config.groups.as_ref().unwrap_or(&vec![]).clone();
-        let arch = arch_config.arch.as_ref().unwrap_or(&vec![]).clone();
-        let backup = arch_config.backup.as_ref().unwrap_or(&vec![]).clone();
-        let depends = arch_config.depends.as_ref().unwrap_or(&vec![]).clone();
-        let makedepends = arch_config.makedepends.as_ref().unwrap_or(&vec![]).clone();
+        let env_arch = std::env::var("CARGO_ARCH_ARCH").ok().filter(|arch| !arch.trim().is_empty());
+        let arch_source = env_arch.as_ref().map(|_| FieldSource::Environment);
+        let arch = match env_arch {
+            Some(value) => {
+                let arch = value.split(',')
+                                .map(|arch| arch.trim().to_string())
+                                .filter(|arch| !arch.is_empty())
+                                .collect::<Vec<String>>();
+                for unknown in arch.iter().filter(|arch| !ARCHES.contains(&arch.as_str())) {
+                    diagnostics.error(
+                        "arch", "unknown-arch",
+                        format!("`{}` from CARGO_ARCH_ARCH is not an Arch Linux architecture, expected one of {}",
+                                unknown, ARCHES.join(", ")));
+                }
+                arch
+            },
+            None => arch_config.arch.as_ref().unwrap_or(&vec![]).clone(),
+        };
+        let mut backup = arch_config.backup.as_ref().unwrap_or(&vec![]).clone();
+        let backup_dir = self.manifest_dir.join(arch_config.backup_dir.as_deref().unwrap_or("."));
+        for pattern in arch_config.backup_glob.iter().flatten() {
+            let files = expand_glob(&backup_dir, pattern);
+            if files.is_empty() {
+                diagnostics.warning(
+                    "backup_glob", "unmatched-backup-glob",
+                    format!("`{}` matches no file in {}", pattern, backup_dir.display()));
+            }
+            for file in files {
+                if !backup.contains(&file) {
+                    backup.push(file);
+                }
+            }
+        }
+        let mut depends = arch_config.depends.as_ref().unwrap_or(&vec![]).clone();
+        let depends_from = match arch_config.depends_from.as_deref() {
+            None => DependsFrom::RuntimeFeatures,
+            Some(name) => DependsFrom::from_name(name).unwrap_or_else(|| {
+                diagnostics.error(
+                    "depends_from", "unknown-depends-policy",
+                    format!("unknown policy `{}`, expected one of `none`, `explicit`, `all-deps`, `runtime-features`",
+                            name));
+                DependsFrom::RuntimeFeatures
+            }),
+        };
+        let features = arch_config.features.clone().unwrap_or_default();
+        let default_features = !arch_config.no_default_features.unwrap_or(false);
+        let (enabled_features, _) = self.enabled_features(&features, default_features);
+        for (feature, packages) in arch_config.feature_depends.iter().flatten() {
+            let known = self.features.as_ref().map(|table| table.contains_key(feature)).unwrap_or(false)
+                        || self.dependencies.iter().flatten().any(|(name, dependency)| {
+                               name == feature && dependency.is_optional()
+                           });
+            if !known {
+                diagnostics.warning(
+                    "feature_depends", "unknown-feature",
+                    format!("`{}` is not a feature of the crate, its depends are never added", feature));
+            } else if depends_from != DependsFrom::None && enabled_features.contains(feature) {
+                for package in packages {
+                    add_package(&mut depends, package, &pkgname);
+                }
+            }
+        }
+        let crate_dependencies = match depends_from {
+            DependsFrom::None | DependsFrom::Explicit => vec![],
+            DependsFrom::AllDeps => self.all_dependencies(),
+            DependsFrom::RuntimeFeatures => self.runtime_dependencies(&features, default_features),
+        };
+        let depmap = arch_config.depmap.clone().unwrap_or_default();
+        for dependency in &crate_dependencies {
+            if let Some(package) = depmap.get(dependency) {
+                add_package(&mut depends, package, &pkgname);
+            }
+        }
+        let vendor = arch_config.vendor.unwrap_or(false);
+        let mut makedepends = arch_config.makedepends.as_ref().unwrap_or(&vec![]).clone();
+        if vendor && !makedepends.iter().any(|dep| dep == "cargo") {
+            makedepends.push("cargo".to_string());
+        }
+        if arch_config.auto_makedepends.unwrap_or(true) {
+            // the MSRV, unless rust is already pinned
+            if let Some(rust_version) = self.package.rust_version.as_ref() {
+                if !makedepends.iter().any(|dep| relation_name(dep) == "rust") {
+                    makedepends.push(format!("rust>={}", rust_version));
+                }
+            }
+            // rust ships cargo
+            if !makedepends.iter().any(|dep| relation_name(dep) == "cargo" || relation_name(dep) == "rust") {
+                makedepends.push("cargo".to_string());
+            }
+        }
         let checkdepends = arch_config.checkdepends.as_ref().unwrap_or(&vec![]).clone();
         let optdepends = arch_config.optdepends.as_ref().unwrap_or(&vec![]).clone();
-        let conflicts = arch_config.conflicts.as_ref().unwrap_or(&vec![]).clone();
-        let provides = arch_config.provides.as_ref().unwrap_or(&vec![]).clone();
-        let replaces = arch_config.replaces.as_ref().unwrap_or(&vec![]).clone();
-        let options = arch_config.options.as_ref().unwrap_or(&vec![]).clone();
-
-        ArchConfig {
-            maintainers: maintainers,
-            pkgname: pkgname,
-            pkgver: pkgver,
-            pkgrel: pkgrel,
-            epoch: epoch,
-            pkgdesc: pkgdesc,
-            url: url,
-            license: license,
-            install: install,
-            changelog: changelog,
-            source: source,
-            validpgpkeys: validpgpkeys,
-            noextract: noextract,
-            md5sums: md5sums,
-            sha1sums: sha1sums,
-            sha256sums: sha256sums,
-            sha384sums: sha384sums,
-            sha512sums: sha512sums,
-            groups: groups,
-            arch: arch,
-            backup: backup,
-            depends: depends,
-            makedepends: makedepends,
-            checkdepends: checkdepends,
-            optdepends: optdepends,
-            conflicts: conflicts,
-            provides: provides,
-            replaces: replaces,
-            options: options,
+        let mut conflicts = arch_config.conflicts.as_ref().unwrap_or(&vec![]).clone();
+        let mut provides = arch_config.provides.as_ref().unwrap_or(&vec![]).clone();
+        let mut replaces = arch_config.replaces.as_ref().unwrap_or(&vec![]).clone();
+        let mut options = arch_config.options.as_ref().unwrap_or(&vec![]).clone();
+        if let Some(debug) = arch_config.debug {
+            options.retain(|option| option != "debug" && option != "!debug");
+            options.push(if debug { "debug" } else { "!debug" }.to_string());
+        }
+        let modeline = arch_config.modeline.unwrap_or(false);
+        let pkgdesc_max_length = arch_config.pkgdesc_max_length.unwrap_or(80);
+        let vcs = arch_config.vcs.unwrap_or(self.vcs);
+        // a `foo-git` package stands in for `foo`
+        if vcs {
+            for array in [&mut provides, &mut conflicts, &mut replaces] {
+                add_package(array, &self.package.name, &pkgname);
+            }
+        }
+        let bins = self.bin_names();
+        if arch_config.provides_bins.unwrap_or(false) {
+            for bin in &bins {
+                add_package(&mut provides, bin, &pkgname);
+            }
+        }
+        // `$pkgver` is left to makepkg, so the entry follows the pkgver
+        if arch_config.versioned_provides.unwrap_or(false) {
+            let base = versioned_base(&pkgname);
+            if base != pkgname && !provides.iter().any(|entry| relation_name(entry) == base) {
+                provides.push(format!("{}=$pkgver", base));
+            }
+        }
+        let template_name = match arch_config.cargo_install.unwrap_or(false) {
+            true => "cargo-install".to_string(),
+            false => detect_template(self, vcs).to_string(),
+        };
+        let include_template = arch_config.include_template.unwrap_or(true);
+        let indent = " ".repeat(arch_config.indent.unwrap_or(2));
+        let newline = match arch_config.newline.as_deref() {
+            None | Some("lf") => "\n".to_string(),
+            Some("crlf") => "\r\n".to_string(),
+            Some(newline) => {
+                diagnostics.error(
+                    "newline", "unknown-newline",
+                    format!("unknown newline style `{}`, expected `lf` or `crlf`", newline));
+                "\n".to_string()
+            },
+        };
+
+        let bin_install = arch_config.bin_install.clone().unwrap_or_default();
+        let bin_dir = bin_install.dir.as_deref()
+                                     .unwrap_or("usr/bin")
+                                     .trim_matches('/')
+                                     .to_string();
+        let bin_mode = bin_install.mode.unwrap_or_else(|| "755".to_string());
+        if !is_octal_mode(&bin_mode) {
+            diagnostics.error(
+                "bin_install", "bin-mode-not-octal",
+                format!("mode `{}` is not octal permissions like `755`", bin_mode));
+        } else if bin_mode.chars().rev().nth(2).map(|owner| owner.to_digit(8).unwrap() & 1 == 0).unwrap_or(true) {
+            diagnostics.warning(
+                "bin_install", "bin-mode-not-executable",
+                format!("mode `{}` doesn't let the owner execute the binaries", bin_mode));
         }
+
+        let mut install_files = arch_config.install_files.clone().unwrap_or_default();
+        for install_file in &install_files {
+            if install_file.dest.starts_with('/') {
+                diagnostics.error(
+                    "install_files", "install-dest-absolute",
+                    format!("dest `{}` is under $pkgdir, drop the leading slash", install_file.dest));
+            }
+            if let Some(mode) = install_file.mode.as_ref().filter(|mode| !is_octal_mode(mode)) {
+                diagnostics.error(
+                    "install_files", "install-mode-not-octal",
+                    format!("mode `{}` of `{}` is not octal permissions like `644`", mode, install_file.src));
+            }
+        }
+
+        for (config, dir) in [(&arch_config.sysusers, "sysusers.d"), (&arch_config.tmpfiles, "tmpfiles.d")] {
+            if let Some(config) = config {
+                install_files.push(CargoInstallFile {
+                    src: config.clone(),
+                    dest: format!("usr/lib/{}/{}.conf", dir, pkgname),
+                    mode: None,
+                });
+            }
+        }
+        let install_script = (arch_config.sysusers.is_some() || arch_config.tmpfiles.is_some()) && install.is_empty();
+        if install_script {
+            install = format!("{}.install", pkgname);
+        } else if arch_config.sysusers.is_some() || arch_config.tmpfiles.is_some() {
+            diagnostics.warning(
+                "install", "install-script-conflict",
+                format!("`{}` is not generated, run systemd-sysusers and systemd-tmpfiles from it", install));
+        }
+
+        let mut provenance = BTreeMap::new();
+
+        macro_rules! add_provenance {
+            ( $fallback: expr, $( $field: ident ),* ) => {
+                $(
+                    provenance.insert(stringify!($field), match arch_config.$field {
+                        Some(_) => FieldSource::Metadata,
+                        None => $fallback,
+                    });
+                )*
+            }
+        }
+
+        add_provenance!(FieldSource::CargoFallback, maintainers, pkgname, pkgver, license);
+        add_provenance!(if pkgdesc.is_empty() { FieldSource::Default } else { FieldSource::CargoFallback },
+                        pkgdesc);
+        add_provenance!(if url.is_empty() { FieldSource::Default } else { FieldSource::CargoFallback },
+                        url);
+        add_provenance!(FieldSource::Default,
+                        pkgrel, epoch, install, changelog, source, validpgpkeys, noextract,
+                        md5sums, sha1sums, sha224sums, sha256sums, sha384sums, sha512sums, b2sums,
+                        groups, arch, backup,
+                        depends, makedepends, checkdepends, optdepends, conflicts, provides, replaces,
+                        options);
+        provenance.insert("pkgrel", pkgrel_source);
+        if let Some(arch_source) = arch_source {
+            provenance.insert("arch", arch_source);
+        }
+
+        let mut config = ArchConfig {
+            maintainers,
+            pkgname,
+            pkgver,
+            pkgrel,
+            epoch,
+            pkgdesc,
+            url,
+            license,
+            install,
+            changelog,
+            source,
+            validpgpkeys,
+            pgpkeys: arch_config.pgpkeys.clone().unwrap_or_default(),
+            noextract,
+            md5sums,
+            sha1sums,
+            sha224sums,
+            sha256sums,
+            sha384sums,
+            sha512sums,
+            b2sums,
+            groups,
+            arch,
+            backup,
+            multilib_libs: match arch_config.multilib.unwrap_or(false) {
+                true => arch_config.multilib_libs.clone().unwrap_or_default(),
+                false => vec![],
+            },
+            depends,
+            makedepends,
+            checkdepends,
+            optdepends,
+            wrap_optdepends: arch_config.wrap_optdepends.unwrap_or(false),
+            conflicts,
+            provides,
+            replaces,
+            options,
+            modeline,
+            pkgdesc_max_length,
+            vcs,
+            template_name: String::new(),
+            crate_name: self.package.name.clone(),
+            crate_version: self.package.version.clone(),
+            include_template,
+            indent,
+            newline,
+            final_newline: true,
+            vendor,
+            auto_apply_patches: arch_config.auto_apply_patches.unwrap_or(false),
+            fetch_in_prepare: arch_config.fetch_in_prepare.unwrap_or(false),
+            features,
+            no_default_features: !default_features,
+            locked: false,
+            manifest_dir: self.manifest_dir.clone(),
+            cargo_target_dir: None,
+            default_target_dir: pkgbuild_target_dir(&self.manifest_dir),
+            output_dir: PathBuf::from("."),
+            pkgbuild_name: "PKGBUILD".to_string(),
+            srcinfo_name: ".SRCINFO".to_string(),
+            only: vec![],
+            target: None,
+            bins,
+            bin_dir,
+            bin_mode,
+            install_files,
+            install_script,
+            sysusers: arch_config.sysusers.clone(),
+            tmpfiles: arch_config.tmpfiles.clone(),
+            lib_name: self.lib_name(),
+            cdylib_provides: arch_config.cdylib_provides.unwrap_or(false),
+            detect_bin_conflicts: arch_config.detect_bin_conflicts.unwrap_or(false),
+            build_dir: arch_config.srcdir.clone(),
+            exclude: vec![],
+            depends_from,
+            crate_dependencies,
+            build_dependencies: if self.has_build_script() { self.build_dependencies() } else { vec![] },
+            links: self.package.links.clone().filter(|_| self.has_build_script()),
+            depmap,
+            diagnostics,
+            provenance,
+        };
+        config.set_template_name(&template_name);
+        config
     }
 }
 
 
 impl GeneratePackageConfig for ArchConfig {
-    fn generate_package_config(&self) {
-        self.generate_pkgbuild();
+    fn generate_package_config(&self) -> Result<(), String> {
+        self.generate_pkgbuild()?;
+        self.generate_install_script()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+    use crate::config::validate::Severity;
+    use crate::pkgbuild::merge_variables;
+    use crate::testing::{TempDir, manifest, with_env};
+
+    /// The config of a `foo` crate with `metadata` as its `[package.metadata.arch]`
+    fn config(metadata: &str) -> ArchConfig {
+        ArchConfig::from_manifest_str(&manifest("foo", metadata), None)
+    }
+
+    /// Merge the variables of `config` into `existing` twice, like `--append-to` run again
+    fn append_twice(config: &ArchConfig, existing: &str) -> String {
+        let merged = merge_variables(existing, &config.render_pkgbuild(), FIELDS).unwrap();
+        assert_eq!(merge_variables(&merged, &config.render_pkgbuild(), FIELDS).unwrap(), merged);
+        merged
+    }
+
+    #[test]
+    fn wrapped_optdepends_are_aligned() {
+        let config = config("wrap_optdepends = true\n\
+                             optdepends = [\"a: for a\", \"bbb: for b\", \"cc:   for c\"]\n");
+
+        assert!(config.render_pkgbuild().contains("optdepends=(\n  \"a:   for a\"\n  \"bbb: for b\"\n  \"cc:  for c\"\n)\n"));
+    }
+
+    #[test]
+    fn wrapped_optdepends_round_trip_through_append() {
+        let config = config("wrap_optdepends = true\noptdepends = [\"bar: for bar\", \"bazzz: for baz\"]\n");
+
+        let merged = append_twice(&config, "_helper() { :; }\npkgname=foo\noptdepends=(\"old: gone\")\n");
+
+        assert!(merged.contains("optdepends=(\n  \"bar:   for bar\"\n  \"bazzz: for baz\"\n)\n"));
+        assert!(!merged.contains("old: gone"));
+    }
+
+    #[test]
+    fn validpgpkeys_are_annotated_with_their_owners() {
+        let config = config("validpgpkeys = [\"ABCD1234\", \"EF567890\"]\n\
+                             [package.metadata.arch.pgpkeys]\n\
+                             \"abcd 1234\" = \"Jane O'Brien <jane@example.org>\"\n");
+
+        assert!(config.render_pkgbuild()
+                      .contains("validpgpkeys=(\n  \"ABCD1234\"  # Jane O'Brien <jane@example.org>\n  \"EF567890\"\n)\n"));
+    }
+
+    #[test]
+    fn annotated_validpgpkeys_round_trip_through_append() {
+        let config = config("validpgpkeys = [\"ABCD1234\"]\n\
+                             [package.metadata.arch.pgpkeys]\n\
+                             ABCD1234 = \"Jane O'Brien\"\n");
+
+        let merged = append_twice(&config, "pkgname=foo\nvalidpgpkeys=(\"OLD\")\n\nbuild() {\n  make\n}\n");
+
+        assert!(merged.contains("validpgpkeys=(\n  \"ABCD1234\"  # Jane O'Brien\n)\n"));
+        assert!(merged.ends_with("\nbuild() {\n  make\n}\n"));
+    }
+
+    #[test]
+    fn target_dir_follows_the_project_config() {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", ""));
+        dir.write("src/main.rs", "fn main() {}\n");
+        dir.write(".cargo/config.toml", "[build]\ntarget-dir = \"out\"\n");
+
+        let config = ArchConfig::new(dir.path().to_str(), None);
+
+        assert!(config.render_pkgbuild().contains("\"$pkgdir/usr/bin\" \"${CARGO_TARGET_DIR:-out}/release/foo\"\n"));
+    }
+
+    #[test]
+    fn rendering_ignores_the_environment() {
+        let dir = TempDir::new();
+        dir.write("project/Cargo.toml", &manifest("foo", ""));
+        dir.write("project/src/main.rs", "fn main() {}\n");
+        dir.write("cargo-home/config.toml", "[build]\ntarget-dir = \"/home/me/tgt\"\n");
+        let render = || ArchConfig::new(dir.path().join("project").to_str(), None).render_pkgbuild();
+
+        let clean = with_env(&[("CARGO_TARGET_DIR", None), ("CARGO_HOME", None)], render);
+        let cargo_home = dir.path().join("cargo-home");
+        let dirty = with_env(&[("CARGO_TARGET_DIR", Some("/home/me/target".as_ref())),
+                               ("CARGO_HOME", Some(cargo_home.as_os_str()))],
+                             render);
+
+        assert_eq!(dirty, clean);
+        assert!(!clean.contains("/home/me"));
+        assert!(clean.contains("\"${CARGO_TARGET_DIR:-target}/release/foo\""));
+    }
+
+    /// The `depends` of a crate needing `openssl-sys`, and `libgit2-sys` with the non-default `git` feature,
+    /// under the `depends_from` policy, with `--depmap` mapping `libz-sys`
+    fn policy_depends(policy: &str) -> Vec<String> {
+        let mut config = ArchConfig::from_manifest_str(&(manifest("foo", &format!("depends_from = \"{}\"\n\
+                                                                                  depends = [\"glibc\"]\n\
+                                                                                  [package.metadata.arch.depmap]\n\
+                                                                                  openssl-sys = \"openssl\"\n\
+                                                                                  libgit2-sys = \"libgit2\"\n",
+                                                                                  policy))
+                                                         + "\n[features]\ngit = [\"libgit2-sys\"]\n\
+                                                            \n[dependencies]\nopenssl-sys = \"0.9\"\nlibz-sys = \"1\"\n\
+                                                            libgit2-sys = { version = \"0.16\", optional = true }\n"),
+                                                       None);
+        let shared = vec![("libz-sys".to_string(), "zlib".to_string())].into_iter().collect();
+        crate::depmap::apply(&mut config, &shared);
+        config.depends
+    }
+
+    #[test]
+    fn depends_from_none_keeps_the_list() {
+        assert_eq!(policy_depends("none"), ["glibc"]);
+    }
+
+    #[test]
+    fn depends_from_explicit_keeps_the_list() {
+        assert_eq!(policy_depends("explicit"), ["glibc"]);
+    }
+
+    #[test]
+    fn depends_from_all_deps_maps_every_dependency() {
+        assert_eq!(policy_depends("all-deps"), ["glibc", "libgit2", "openssl", "zlib"]);
+    }
+
+    #[test]
+    fn depends_from_runtime_features_maps_the_linked_dependencies() {
+        assert_eq!(policy_depends("runtime-features"), ["glibc", "openssl", "zlib"]);
+    }
+
+    #[test]
+    fn depends_from_rejects_unknown_policies() {
+        let config = config("depends_from = \"some\"\n");
+
+        assert!(config.diagnostics.has_errors());
+    }
+
+    /// The `depends` of a crate with a `gui` feature needing `gtk3`, built with `features`, under the `depends_from` policy
+    fn feature_depends(policy: &str, features: &str) -> Vec<String> {
+        ArchConfig::from_manifest_str(&(manifest("foo", &format!("depends_from = \"{}\"\nfeatures = [{}]\n\
+                                                                 [package.metadata.arch.feature_depends]\n\
+                                                                 gui = [\"gtk3\"]\n",
+                                                                 policy, features))
+                                        + "\n[features]\ngui = []\n"),
+                                      None)
+            .depends
+    }
+
+    #[test]
+    fn feature_depends_follow_the_enabled_features() {
+        assert_eq!(feature_depends("runtime-features", "\"gui\""), ["gtk3"]);
+        assert!(feature_depends("runtime-features", "").is_empty());
+    }
+
+    #[test]
+    fn feature_depends_follow_depends_from() {
+        assert!(feature_depends("none", "\"gui\"").is_empty());
+        assert_eq!(feature_depends("explicit", "\"gui\""), ["gtk3"]);
+        assert_eq!(feature_depends("all-deps", "\"gui\""), ["gtk3"]);
+    }
+
+    #[test]
+    fn crlf_and_four_space_indent() {
+        let config = config("newline = \"crlf\"\nindent = 4\nmodeline = true\n\
+                             wrap_optdepends = true\noptdepends = [\"bar: for bar\"]\n");
+
+        let rendered = config.render_pkgbuild();
+
+        assert!(rendered.contains("\r\noptdepends=(\r\n    \"bar: for bar\"\r\n)\r\n"));
+        assert!(rendered.contains("\r\nbuild() {\r\n    cd ..\r\n"));
+        assert!(rendered.ends_with("}\r\n\r\n# vim:set ts=4 sw=4 et:\r\n"));
+        assert!(!rendered.replace("\r\n", "").contains('\n'));
+    }
+
+    #[test]
+    fn modeline_is_the_last_line_when_enabled() {
+        assert!(!config("").render_pkgbuild().contains("vim:set"));
+
+        let rendered = config("modeline = true\n").render_pkgbuild();
+        assert_eq!(rendered.lines().last(), Some("# vim:set ts=2 sw=2 et:"));
+        assert_eq!(rendered.matches("vim:set").count(), 1);
+    }
+
+
+    /// `config` with `package` appended to `[package]`
+    fn config_with_package(package: &str, metadata: &str) -> ArchConfig {
+        let manifest = manifest("foo", metadata).replacen("license = \"MIT\"\n", &format!("license = \"MIT\"\n{}", package), 1);
+        ArchConfig::from_manifest_str(&manifest, None)
+    }
+
+    #[test]
+    fn pkgdesc_from_keywords_when_the_description_is_empty() {
+        let package = "keywords = [\"cli\", \"arch\"]\ncategories = [\"command-line-utilities\"]\n";
+        let undescribed = |metadata: &str| ArchConfig::from_manifest_str(
+            &manifest("foo", metadata).replace("description = \"A foo\"\n", package), None);
+
+        assert_eq!(undescribed("pkgdesc_from_keywords = true\n").pkgdesc, "cli, arch, command-line-utilities");
+        assert_eq!(undescribed("").pkgdesc, "");
+        assert_eq!(config_with_package(package, "pkgdesc_from_keywords = true\n").pkgdesc, "A foo");
+        assert_eq!(undescribed("pkgdesc_from_keywords = true\npkgdesc = \"Mine\"\n").pkgdesc, "Mine");
+    }
+
+    #[test]
+    fn url_follows_url_from() {
+        let package = "homepage = \"https://foo.org\"\nrepository = \"https://git.foo.org\"\n\
+                       documentation = \"https://docs.foo.org\"\n";
+
+        assert_eq!(config_with_package(package, "").url, "https://foo.org");
+        assert_eq!(config_with_package(package, "url_from = [\"documentation\", \"homepage\"]\n").url, "https://docs.foo.org");
+        assert_eq!(config_with_package("repository = \"https://git.foo.org\"\n", "").url, "https://git.foo.org");
+        assert_eq!(config_with_package(package, "url_from = [\"documentation\"]\nurl = \"https://mine.org\"\n").url,
+                   "https://mine.org");
+
+        let unknown = config_with_package(package, "url_from = [\"homepgae\"]\n");
+        assert_eq!(unknown.url, "");
+        assert!(unknown.diagnostics.iter().any(|diagnostic| diagnostic.check == "unknown-url-field"));
+    }
+
+    #[test]
+    fn url_from_takes_the_first_set_key() {
+        let package = "homepage = \"https://foo.org\"\nrepository = \"https://git.foo.org\"\n";
+
+        assert_eq!(config_with_package(package, "url_from = [\"repository\", \"homepage\"]\n").url, "https://git.foo.org");
+    }
+
+
+    #[test]
+    fn signed_sources_are_annotated() {
+        let rendered = config("source = [\"https://example.org/foo-$pkgver.tar.gz\", \"https://example.org/foo-$pkgver.tar.gz.sig\", \
+                                          \"README.asc\"]\n\
+                               validpgpkeys = [\"ABCD1234\"]\n").render_pkgbuild();
+
+        assert!(rendered.contains("# foo-$pkgver.tar.gz is verified by foo-$pkgver.tar.gz.sig with key(s): ABCD1234\nsource=("));
+        assert!(!rendered.contains("README is verified"));
+        assert!(!config("source = [\"foo.tar.gz\", \"foo.tar.gz.sig\"]\n").render_pkgbuild().contains("is verified by"));
+    }
+
+
+    #[test]
+    fn vendor_prepares_an_offline_build() {
+        let vendored = config("vendor = true\n");
+        let rendered = vendored.render_pkgbuild();
+
+        assert!(rendered.contains("prepare() {\n  cd ..\n  mkdir -p .cargo\n  cargo vendor --locked > .cargo/config.toml\n}\n"));
+        assert!(rendered.contains("cargo build --release --offline"));
+        assert!(vendored.makedepends.contains(&"cargo".to_string()));
+
+        let rendered = config("").render_pkgbuild();
+        assert!(!rendered.contains("prepare()"));
+        assert!(!rendered.contains("--offline"));
+    }
+
+
+    #[test]
+    fn metadata_from_another_section() {
+        let manifest = manifest("foo", "pkgname = \"foo-aur\"\n") + "\n[package.metadata.arch_internal]\npkgname = \"foo-internal\"\n";
+
+        assert_eq!(ArchConfig::from_manifest_str(&manifest, None).pkgname, "foo-aur");
+        assert_eq!(ArchConfig::from_manifest_str(&manifest, Some("arch_internal")).pkgname, "foo-internal");
+    }
+
+
+    #[test]
+    fn provenance_tells_set_fields_from_defaults() {
+        let defaulted = config("");
+        assert_eq!(defaulted.provenance()["pkgrel"], FieldSource::Default);
+        assert_eq!(defaulted.provenance()["pkgname"], FieldSource::CargoFallback);
+        assert_eq!(defaulted.provenance()["pkgdesc"], FieldSource::CargoFallback);
+
+        let set = config("pkgrel = \"2\"\npkgname = \"bar\"\npkgdesc = \"A bar\"\n");
+        assert_eq!(set.provenance()["pkgrel"], FieldSource::Metadata);
+        assert_eq!(set.provenance()["pkgname"], FieldSource::Metadata);
+        assert_eq!(set.provenance()["pkgdesc"], FieldSource::Metadata);
+
+        let undescribed = ArchConfig::from_manifest_str(&manifest("foo", "").replace("description = \"A foo\"\n", ""), None);
+        assert_eq!(undescribed.provenance()["pkgdesc"], FieldSource::Default);
+
+        assert_eq!(config("default_pkgrel = \"3\"\n").provenance()["pkgrel"], FieldSource::Metadata);
+
+        let mut overridden = config("pkgrel = \"2\"\narch = [\"x86_64\"]\n");
+        overridden.set_pkgrel("4");
+        overridden.set_target("aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!(overridden.provenance()["pkgrel"], FieldSource::CommandLine);
+        assert_eq!(overridden.provenance()["arch"], FieldSource::CommandLine);
+        assert_eq!(overridden.provenance()["depends"], FieldSource::Default);
+    }
+
+
+    #[test]
+    fn srcinfo_name_follows_the_pkgbuild_name() {
+        let mut config = config("");
+
+        config.set_pkgbuild_name("PKGBUILD.generated", None);
+        assert_eq!(config.srcinfo_name, ".SRCINFO.generated");
+        config.set_pkgbuild_name("foo", None);
+        assert_eq!(config.srcinfo_name, "foo.SRCINFO");
+        config.set_pkgbuild_name("foo", Some("SRCINFO"));
+        assert_eq!(config.srcinfo_name, "SRCINFO");
+        assert_eq!(config.pkgbuild_path(), PathBuf::from("./foo"));
+    }
+
+
+    #[test]
+    fn vcs_package_stands_in_for_its_base_once() {
+        let config = config("vcs = true\npkgname = \"foo-git\"\nprovides = [\"foo>=1.0\"]\n");
+
+        assert_eq!(config.provides, ["foo>=1.0"]);
+        assert_eq!(config.conflicts, ["foo"]);
+        assert_eq!(config.replaces, ["foo"]);
+    }
+
+    #[test]
+    fn vcs_package_never_stands_in_for_itself() {
+        let config = config("vcs = true\npkgname = \"foo\"\n");
+
+        assert!(config.provides.is_empty());
+        assert!(config.conflicts.is_empty());
+        assert!(config.replaces.is_empty());
+    }
+
+    #[test]
+    fn add_package_skips_listed_packages_and_itself() {
+        let mut array = vec!["bar: for bar".to_string()];
+
+        add_package(&mut array, "bar", "foo");
+        add_package(&mut array, "foo", "foo");
+        add_package(&mut array, "baz", "foo");
+
+        assert_eq!(array, ["bar: for bar", "baz"]);
+    }
+
+
+    #[test]
+    fn msrv_goes_into_makedepends() {
+        let package = "rust-version = \"1.70\"\n";
+
+        assert_eq!(config_with_package(package, "").makedepends, ["rust>=1.70"]);
+        assert_eq!(config_with_package(package, "makedepends = [\"rust>=1.75\", \"clang\"]\n").makedepends,
+                   ["rust>=1.75", "clang"]);
+    }
+
+
+    /// The variables a rendered PKGBUILD assigns, in order
+    fn variables(rendered: &str) -> Vec<&str> {
+        rendered.lines()
+                .filter_map(|line| line.split_once('=').map(|(name, _)| name))
+                .filter(|name| name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+                .collect()
+    }
+
+    #[test]
+    fn only_restricts_the_fields() {
+        let mut config = config("arch = [\"x86_64\"]\ndepends = [\"glibc\"]\nsource = [\"a.txt\"]\nsha256sums = [\"SKIP\"]\n");
+        config.only = parse_fields("depends,source,sha256sums").unwrap();
+
+        assert_eq!(variables(&config.render_pkgbuild()),
+                   ["pkgname", "pkgver", "pkgrel", "source", "sha256sums", "arch", "depends"]);
+    }
+
+    #[test]
+    fn only_rejects_unknown_fields() {
+        assert_eq!(parse_fields("depends, sources").unwrap_err(), "unknown field `sources`");
+    }
+
+
+    #[test]
+    fn exclude_suppresses_the_fields_but_the_mandatory_ones() {
+        let mut config = config("arch = [\"x86_64\"]\ndepends = [\"glibc\"]\n");
+        config.exclude = parse_fields("depends,epoch,arch").unwrap();
+
+        let rendered = config.render_pkgbuild();
+        let variables = variables(&rendered);
+
+        assert!(!variables.contains(&"depends") && !variables.contains(&"epoch"));
+        assert!(variables.contains(&"makedepends") && variables.contains(&"arch"));
+    }
+
+
+    #[test]
+    fn maintainers_from_authors_in_either_form() {
+        let strings = config_with_package("authors = [\"Jane  Doe<jane@example.org>\", \"Alice\"]\n", "");
+        assert_eq!(strings.maintainers, ["Jane Doe <jane@example.org>", "Alice"]);
+
+        let tables = config_with_package("authors = [{ name = \"Bob\", email = \"bob@example.org\" }, { name = \" Alice \" }]\n", "");
+        assert_eq!(tables.maintainers, ["Bob <bob@example.org>", "Alice"]);
+        assert!(tables.render_pkgbuild().contains("\n# Maintainer: Bob <bob@example.org>\n# Maintainer: Alice\n"));
+    }
+
+    #[test]
+    fn maintainers_in_the_metadata_take_precedence() {
+        let config = config_with_package("authors = [\"Jane <jane@example.org>\"]\n",
+                                         "maintainers = [{ name = \"Bob\", email = \"bob@example.org\" }]\n");
+
+        assert_eq!(config.maintainers, ["Bob <bob@example.org>"]);
+    }
+
+
+    /// The config of a `foo` binary crate on disk, with `metadata` as its `[package.metadata.arch]`
+    fn bin_config(dir: &TempDir, metadata: &str) -> ArchConfig {
+        dir.write("Cargo.toml", &manifest("foo", metadata));
+        dir.write("src/main.rs", "fn main() {}\n");
+        ArchConfig::new(dir.path().to_str(), None)
+    }
+
+    #[test]
+    fn target_sets_arch_and_the_artifact_path() {
+        let dir = TempDir::new();
+
+        let mut aarch64 = bin_config(&dir, "arch = [\"x86_64\"]\n");
+        aarch64.set_target("aarch64-unknown-linux-gnu").unwrap();
+        let rendered = aarch64.render_pkgbuild();
+        assert!(rendered.contains("\narch=(\"aarch64\")\n"));
+        assert!(rendered.contains("cargo build --release --target aarch64-unknown-linux-gnu\n"));
+        assert!(rendered.contains("\"${CARGO_TARGET_DIR:-target}/aarch64-unknown-linux-gnu/release/foo\""));
+
+        let mut i686 = bin_config(&dir, "");
+        i686.set_target("i686-unknown-linux-gnu").unwrap();
+        let rendered = i686.render_pkgbuild();
+        assert!(rendered.contains("\narch=(\"i686\")\n"));
+        assert!(rendered.contains("\"${CARGO_TARGET_DIR:-target}/i686-unknown-linux-gnu/release/foo\""));
+
+        assert!(bin_config(&dir, "").render_pkgbuild().contains("\"${CARGO_TARGET_DIR:-target}/release/foo\""));
+        assert!(bin_config(&dir, "").set_target("wasm32-unknown-unknown").is_err());
+    }
+
+
+    #[test]
+    fn bin_install_sets_the_dir_and_mode() {
+        let dir = TempDir::new();
+
+        let config = bin_config(&dir, "[package.metadata.arch.bin_install]\ndir = \"/usr/lib/foo/\"\nmode = \"750\"\n");
+
+        assert!(config.render_pkgbuild()
+                      .contains("  install -Dm750 -t \"$pkgdir/usr/lib/foo\" \"${CARGO_TARGET_DIR:-target}/release/foo\"\n"));
+        assert!(config.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn bin_install_mode_must_be_executable_octal() {
+        let dir = TempDir::new();
+        let checks = |mode: &str| bin_config(&dir, &format!("[package.metadata.arch.bin_install]\nmode = \"{}\"\n", mode))
+                                      .diagnostics
+                                      .iter()
+                                      .map(|diagnostic| diagnostic.check)
+                                      .collect::<Vec<&str>>();
+
+        assert_eq!(checks("rwx"), ["bin-mode-not-octal"]);
+        assert_eq!(checks("0644"), ["bin-mode-not-executable"]);
+        assert!(checks("0755").is_empty());
+    }
+
+
+    #[test]
+    fn cargo_is_a_default_makedepend() {
+        assert_eq!(config("").makedepends, ["cargo"]);
+        assert_eq!(config("makedepends = [\"rust\"]\n").makedepends, ["rust"]);
+        assert_eq!(config("makedepends = [\"clang\"]\n").makedepends, ["clang", "cargo"]);
+    }
+
+    #[test]
+    fn auto_makedepends_can_be_turned_off() {
+        assert!(config("auto_makedepends = false\n").makedepends.is_empty());
+        assert!(config_with_package("rust-version = \"1.70\"\n", "auto_makedepends = false\n").makedepends.is_empty());
+    }
+
+
+    #[test]
+    fn build_functions_cd_into_the_srcdir() {
+        let rendered = config("srcdir = \"$pkgname-v$pkgver\"\n").render_pkgbuild();
+        let functions = rendered.matches("() {\n").count();
+        assert!(functions > 0);
+        assert_eq!(rendered.matches("() {\n  cd \"$srcdir/$pkgname-v$pkgver\"\n").count(), functions, "{}", rendered);
+        assert!(!rendered.contains("cd ..\n"));
+
+        assert!(config("").render_pkgbuild().contains("  cd ..\n"));
+    }
+
+
+    #[test]
+    fn debug_output_names_the_debug_package() {
+        let debug = config("debug = true\noptions = [\"!lto\", \"!debug\"]\n");
+        assert_eq!(debug.options, ["!lto", "debug"]);
+        assert_eq!(debug.debug_pkgname().unwrap(), "foo-debug");
+        assert!(debug.render_pkgbuild().contains("# makepkg also builds foo-debug from the debug symbols\n\
+                                                  options=(\"!lto\", \"debug\")\n"));
+
+        let stripped = config("debug = false\noptions = [\"debug\"]\n");
+        assert_eq!(stripped.options, ["!debug"]);
+        assert_eq!(stripped.debug_pkgname(), None);
+        assert!(!stripped.render_pkgbuild().contains("foo-debug"));
+
+        assert_eq!(config("options = [\"debug\", \"!debug\"]\n").debug_pkgname(), None);
+    }
+
+
+    #[test]
+    fn provides_bins_adds_the_binary_names() {
+        let dir = TempDir::new();
+        let tools = |metadata: &str| {
+            dir.write("Cargo.toml", &(manifest("foo-tools", metadata)
+                                      + "\n[[bin]]\nname = \"foo\"\npath = \"src/foo.rs\"\n"));
+            dir.write("src/foo.rs", "fn main() {}\n");
+            ArchConfig::new(dir.path().to_str(), None)
+        };
+
+        assert_eq!(tools("provides_bins = true\n").provides, ["foo"]);
+        assert_eq!(tools("provides_bins = true\nprovides = [\"foo\", \"bar\"]\n").provides, ["foo", "bar"]);
+        assert!(tools("").provides.is_empty());
+    }
+
+
+    #[test]
+    fn pkgrel_default_from_the_environment_then_the_metadata() {
+        let pkgrel = |metadata: &str, env: Option<&str>| {
+            with_env(&[("CARGO_ARCH_PKGREL", env.map(OsStr::new))], || config(metadata).pkgrel)
+        };
+
+        assert_eq!(pkgrel("pkgrel = \"4\"\ndefault_pkgrel = \"3\"\n", Some("2")), "4");
+        assert_eq!(pkgrel("default_pkgrel = \"3\"\n", Some("2")), "2");
+        assert_eq!(pkgrel("default_pkgrel = \"3\"\n", Some("")), "3");
+        assert_eq!(pkgrel("", Some("")), "1");
+        assert_eq!(pkgrel("", None), "1");
+    }
+
+
+    #[test]
+    fn cargo_install_template_installs_the_published_crate() {
+        let install = "  cargo install --no-track --locked --root \"$pkgdir/usr\" --version 1.0.0 foo\n";
+
+        let flagged = config("cargo_install = true\nauto_makedepends = false\n");
+        assert_eq!(flagged.template_name, "cargo-install");
+        assert_eq!(flagged.makedepends, ["cargo"]);
+        assert!(flagged.render_pkgbuild().contains(install), "{}", flagged.render_pkgbuild());
+
+        let mut named = config("makedepends = [\"rust\"]\n");
+        named.set_template_name("cargo-install");
+        assert_eq!(named.makedepends, ["rust"]);
+        assert!(named.render_pkgbuild().contains(install));
+    }
+
+
+    #[test]
+    fn rendering_is_deterministic() {
+        let manifest = manifest("foo", "validpgpkeys = [\"AAAA\", \"BBBB\", \"CCCC\", \"DDDD\"]\n\
+                                        depends_from = \"all-deps\"\n\
+                                        [package.metadata.arch.pgpkeys]\n\
+                                        DDDD = \"Dan\"\nBBBB = \"Bob\"\nCCCC = \"Cid\"\nAAAA = \"Ann\"\n\
+                                        [package.metadata.arch.depmap]\n\
+                                        zstd = \"zstd\"\nopenssl = \"openssl\"\nlibgit2-sys = \"libgit2\"\nxz2 = \"xz\"\n")
+                       + "\n[dependencies]\nxz2 = \"0.1\"\nzstd = \"0.13\"\nlibgit2-sys = \"0.16\"\nopenssl = \"0.10\"\n";
+        let render = || ArchConfig::from_manifest_str(&manifest, None).render_pkgbuild();
+
+        let rendered = render();
+        assert!((0..20).all(|_| render() == rendered));
+        assert!(rendered.contains("\ndepends=(\"libgit2\", \"openssl\", \"xz\", \"zstd\")\n"), "{}", rendered);
+        assert!(rendered.contains("\"AAAA\"  # Ann\n  \"BBBB\"  # Bob\n  \"CCCC\"  # Cid\n  \"DDDD\"  # Dan\n"));
+    }
+
+
+    #[test]
+    fn pkgbuild_ends_with_exactly_one_newline() {
+        let mut config = config("");
+        assert!(config.render_pkgbuild().ends_with("}\n"));
+
+        config.include_template = false;
+        let rendered = config.render_pkgbuild();
+        assert!(rendered.ends_with('\n') && !rendered.ends_with("\n\n"), "{:?}", rendered);
+
+        config.final_newline = false;
+        assert!(!config.render_pkgbuild().ends_with('\n'));
+
+        assert_eq!(final_newline("pkgname=foo\r\n\r\n\n", Some("\r\n")), "pkgname=foo\r\n");
+        assert_eq!(final_newline("pkgname=foo", Some("\n")), "pkgname=foo\n");
+        assert_eq!(final_newline("pkgname=foo\n\n", None), "pkgname=foo");
+    }
+
+
+    #[test]
+    fn install_files_are_installed_in_package() {
+        let rendered = config("[[package.metadata.arch.install_files]]\n\
+                               src = \"foo.desktop\"\ndest = \"usr/share/applications/foo.desktop\"\n\
+                               [[package.metadata.arch.install_files]]\n\
+                               src = \"assets/foo.svg\"\ndest = \"usr/share/icons/hicolor/scalable/apps/foo.svg\"\n\
+                               mode = \"0644\"\n").render_pkgbuild();
+
+        assert!(rendered.contains("  install -Dm644 \"foo.desktop\" \"$pkgdir/usr/share/applications/foo.desktop\"\n  \
+                                   install -Dm0644 \"assets/foo.svg\" \"$pkgdir/usr/share/icons/hicolor/scalable/apps/foo.svg\"\n}\n"),
+                "{}", rendered);
+    }
+
+    #[test]
+    fn install_files_need_a_relative_dest_and_an_octal_mode() {
+        let config = config("[[package.metadata.arch.install_files]]\n\
+                             src = \"foo.desktop\"\ndest = \"/usr/share/applications/foo.desktop\"\nmode = \"rw-r--r--\"\n");
+        let messages = config.diagnostics.iter()
+                                         .filter(|diagnostic| diagnostic.field == "install_files")
+                                         .map(|diagnostic| diagnostic.message.as_str())
+                                         .collect::<Vec<&str>>();
+
+        assert_eq!(messages, ["dest `/usr/share/applications/foo.desktop` is under $pkgdir, drop the leading slash",
+                              "mode `rw-r--r--` of `foo.desktop` is not octal permissions like `644`"]);
+    }
+
+
+    /// The `(check, message)` of each diagnostic `to_config` reported
+    fn config_diagnostics(config: &ArchConfig) -> Vec<(&'static str, String)> {
+        config.diagnostics.iter().map(|diagnostic| (diagnostic.check, diagnostic.message.clone())).collect()
+    }
+
+    #[test]
+    fn aliases_set_their_field_with_a_warning() {
+        let config = config("deps = [\"openssl\"]\ndescr = \"Foo tool\"\n");
+
+        assert_eq!(config.depends, ["openssl"]);
+        assert_eq!(config.pkgdesc, "Foo tool");
+        assert_eq!(config_diagnostics(&config), [("alias-key", "`deps` is an alias of `depends`, rename it".to_string()),
+                                                 ("alias-key", "`descr` is an alias of `pkgdesc`, rename it".to_string())]);
+        assert!(config.diagnostics.iter().all(|diagnostic| diagnostic.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected_with_a_suggestion() {
+        let config = config("dependz = [\"openssl\"]\nfrobnicate = true\n");
+
+        assert!(config.depends.is_empty());
+        assert_eq!(config_diagnostics(&config),
+                   [("unknown-key", "unknown key `dependz` in [package.metadata.arch], did you mean `depends`?".to_string()),
+                    ("unknown-key", "unknown key `frobnicate` in [package.metadata.arch]".to_string())]);
+        assert!(config.diagnostics.has_errors());
+    }
+
+
+    #[test]
+    fn patch_sources_are_applied_in_order() {
+        let metadata = "source = [\"https://example.org/foo-1.0.0.tar.gz\", \"fix-build.patch\", \
+                                  \"musl.diff::https://example.org/commit/1234.patch\"]\n";
+
+        let rendered = config(&format!("{}auto_apply_patches = true\n", metadata)).render_pkgbuild();
+        assert!(rendered.contains("prepare() {\n  cd ..\n  patch -Np1 -i \"$srcdir/fix-build.patch\"\n  \
+                                   patch -Np1 -i \"$srcdir/musl.diff\"\n}\n"),
+                "{}", rendered);
+
+        assert!(!config(metadata).render_pkgbuild().contains("patch -Np1"));
+    }
+
+
+    #[test]
+    fn maintainer_lines_of_names_emails_or_both() {
+        let maintainers = |authors: &str| config_with_package(&format!("authors = [{}]\n", authors), "").render_pkgbuild();
+
+        assert!(maintainers("\"  Jane   Doe \"").contains("\n# Maintainer: Jane Doe\n"));
+        assert!(maintainers("\" jane@example.org \"").contains("\n# Maintainer: <jane@example.org>\n"));
+        assert!(maintainers("\"<jane@example.org>\"").contains("\n# Maintainer: <jane@example.org>\n"));
+        assert!(maintainers("{ name = \"\", email = \"jane@example.org\" }").contains("\n# Maintainer: <jane@example.org>\n"));
+        assert!(maintainers("{ email = \"jane@example.org\" }").contains("\n# Maintainer: <jane@example.org>\n"));
+        assert!(maintainers("\" Jane Doe  < jane@example.org > \"").contains("\n# Maintainer: Jane Doe <jane@example.org>\n"));
+    }
+
+
+    #[test]
+    fn source_tables_carry_their_checksums() {
+        let config = config("sha512sums = [\"e1\", \"e2\", \"e3\"]\n\
+                             [[package.metadata.arch.source]]\n\
+                             url = \"https://example.org/foo-1.0.0.tar.gz\"\nsha256 = \"a1\"\nb2 = \"b1\"\n\
+                             [[package.metadata.arch.source]]\n\
+                             url = \"https://example.org/commit/1234.patch\"\nrename = \"fix.patch\"\nsha256 = \"a2\"\n\
+                             [[package.metadata.arch.source]]\n\
+                             url = \"git+https://example.org/foo-assets.git\"\n");
+
+        assert_eq!(config.source, ["https://example.org/foo-1.0.0.tar.gz",
+                                   "fix.patch::https://example.org/commit/1234.patch",
+                                   "git+https://example.org/foo-assets.git"]);
+        assert_eq!(config.sha256sums, ["a1", "a2", "SKIP"]);
+        assert_eq!(config.b2sums, ["b1", "SKIP", "SKIP"]);
+        assert_eq!(config.sha512sums, ["e1", "e2", "e3"]);
+        assert!(config.md5sums.is_empty());
+    }
+
+    #[test]
+    fn source_tables_fill_in_the_flat_checksums() {
+        let flat = config("source = [\"https://example.org/foo-1.0.0.tar.gz\", \"foo.service\"]\n\
+                           sha256sums = [\"a1\", \"a2\"]\n");
+        assert_eq!(flat.sha256sums, ["a1", "a2"]);
+
+        let tables = config("sha256sums = [\"a1\", \"a2\"]\n\
+                             [[package.metadata.arch.source]]\nurl = \"https://example.org/foo-1.0.0.tar.gz\"\n\
+                             [[package.metadata.arch.source]]\nurl = \"foo.service\"\nsha256 = \"c2\"\n");
+        assert_eq!(tables.sha256sums, ["a1", "c2"]);
+    }
+
+
+    #[test]
+    fn backup_glob_expands_over_the_config_tree() {
+        let dir = TempDir::new();
+        dir.write("dist/etc/foo/foo.conf", "");
+        dir.write("dist/etc/foo/conf.d/50-defaults.conf", "");
+        dir.write("dist/etc/foo/foo.conf.example", "");
+        dir.write("dist/usr/share/foo/default.conf", "");
+        let config = bin_config(&dir, "backup = [\"etc/foo/keys\"]\nbackup_dir = \"dist\"\n\
+                                       backup_glob = [\"etc/**/*.conf\", \"/etc/foo/*.toml\"]\n");
+
+        assert_eq!(config.backup, ["etc/foo/keys", "etc/foo/conf.d/50-defaults.conf", "etc/foo/foo.conf"]);
+        let unmatched = config.diagnostics.iter()
+                                          .filter(|diagnostic| diagnostic.check == "unmatched-backup-glob")
+                                          .map(|diagnostic| diagnostic.message.as_str())
+                                          .collect::<Vec<&str>>();
+        assert_eq!(unmatched.len(), 1);
+        assert!(unmatched[0].contains("/etc/foo/*.toml"), "{}", unmatched[0]);
+    }
+
+    #[test]
+    fn backup_glob_skips_target_and_git() {
+        let dir = TempDir::new();
+        dir.write("etc/foo/foo.conf", "");
+        dir.write("target/release/etc/foo/foo.conf", "");
+        dir.write(".git/etc/foo.conf", "");
+        let config = bin_config(&dir, "backup_glob = [\"**/*.conf\"]\n");
+
+        assert_eq!(config.backup, ["etc/foo/foo.conf"]);
+    }
+
+
+    #[test]
+    fn multilib_prefixes_only_the_configured_libraries() {
+        let multilib = "depends = [\"zlib\", \"openssl>=3\", \"bash\"]\nmultilib_libs = [\"zlib\", \"openssl\"]\n";
+
+        let x86_64 = config(&format!("arch = [\"x86_64\"]\nmultilib = true\n{}", multilib)).render_pkgbuild();
+        assert!(x86_64.contains("\ndepends=(\"lib32-zlib\", \"lib32-openssl>=3\", \"bash\")\n"), "{}", x86_64);
+
+        let aarch64 = config(&format!("arch = [\"aarch64\"]\nmultilib = true\n{}", multilib));
+        assert!(aarch64.render_pkgbuild().contains("\ndepends=(\"zlib\", \"openssl>=3\", \"bash\")\n"));
+        assert!(aarch64.validate().iter().any(|diagnostic| diagnostic.field == "multilib"));
+
+        let off = config(&format!("arch = [\"x86_64\"]\n{}", multilib)).render_pkgbuild();
+        assert!(off.contains("\ndepends=(\"zlib\", \"openssl>=3\", \"bash\")\n"));
+    }
+
+
+    #[test]
+    fn versioned_provides_follow_the_pkgver() {
+        let foo2 = config("pkgname = \"foo2\"\nversioned_provides = true\n");
+        assert_eq!(foo2.provides, ["foo=$pkgver"]);
+        assert!(foo2.render_pkgbuild().contains("\nprovides=(\"foo=$pkgver\")\n"));
+
+        assert_eq!(config("pkgname = \"foo-1.0\"\nversioned_provides = true\n").provides, ["foo=$pkgver"]);
+        assert_eq!(config("pkgname = \"foo2\"\nversioned_provides = true\nprovides = [\"foo=2.0\"]\n").provides, ["foo=2.0"]);
+        assert!(config("versioned_provides = true\n").provides.is_empty());
+        assert!(config("pkgname = \"foo2\"\n").provides.is_empty());
+    }
+
+
+    #[test]
+    fn fetch_in_prepare_runs_cargo_fetch() {
+        let rendered = config("fetch_in_prepare = true\n").render_pkgbuild();
+        assert!(rendered.contains("\nprepare() {\n  cd ..\n  cargo fetch --locked\n}\n"), "{}", rendered);
+
+        let mut cross = config("fetch_in_prepare = true\n");
+        cross.set_target("aarch64-unknown-linux-gnu").unwrap();
+        assert!(cross.render_pkgbuild().contains("\n  cargo fetch --locked --target aarch64-unknown-linux-gnu\n"));
+
+        assert!(!config("").render_pkgbuild().contains("prepare()"));
     }
 }
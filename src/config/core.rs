@@ -1,5 +1,11 @@
 //! Basic Rust package's config, modified from Cargo.
 
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use toml;
+
 use super::meta::CargoMetadata;
 
 
@@ -7,6 +13,21 @@ use super::meta::CargoMetadata;
 #[derive(Clone, Debug, Deserialize)]
 pub struct Cargo {
     pub package: CargoPackage,
+    pub lib: Option<CargoTarget>,
+    pub bin: Option<Vec<CargoTarget>>,
+    pub dependencies: Option<BTreeMap<String, CargoDependency>>,
+    #[serde(rename = "build-dependencies")]
+    pub build_dependencies: Option<BTreeMap<String, CargoDependency>>,
+    pub features: Option<BTreeMap<String, Vec<String>>>,
+    /// Directory holding the Cargo.toml, not part of the manifest itself
+    #[serde(skip)]
+    pub manifest_dir: PathBuf,
+    /// Which `[package.metadata.<name>]` holds the arch metadata, `arch` when unset
+    #[serde(skip)]
+    pub metadata_section: Option<String>,
+    /// Package the VCS head as if the metadata said `vcs = true`, named `<name>-git` unless `pkgname` is set
+    #[serde(skip)]
+    pub vcs: bool,
 }
 
 /// data in `[package]` section
@@ -14,17 +35,465 @@ pub struct Cargo {
 pub struct CargoPackage {
     pub name: String,
     pub version: String,
+    #[serde(default)]
     pub description: String,
-    pub authors: Vec<String>,
+    #[serde(default)]
+    pub authors: Vec<CargoAuthor>,
     pub license: String,    // Multiple licenses are separated by `/`
+    #[serde(default)]
     pub readme: String,
     pub homepage: Option<String>,
     pub documentation: Option<String>,
     pub repository: Option<String>,
     pub keywords: Option<Vec<String>>,
+    pub categories: Option<Vec<String>>,
+    #[serde(rename = "rust-version")]
+    pub rust_version: Option<String>,
+    /// The native library the build script links, `links = "ssl"`
+    pub links: Option<String>,
+    /// The build script, a path or `false` to disable the `build.rs` convention
+    pub build: Option<toml::Value>,
     pub metadata: Option<CargoMetadata>,
 }
 
+/// data in `[lib]` and `[[bin]]` sections
+#[derive(Clone, Debug, Deserialize)]
+pub struct CargoTarget {
+    pub name: Option<String>,
+    pub path: Option<String>,
+}
+
+/// a person, either `"Name <email>"` or a `{ name, email }` table
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum CargoAuthor {
+    Name(String),
+    Detailed {
+        #[serde(default)]
+        name: String,
+        email: Option<String>,
+    },
+}
+
+impl CargoAuthor {
+    /// `Name <email>`, with the spacing of hand written entries straightened out,
+    /// `<email>` for an email alone, bracketed or not, and the bare name without one
+    pub fn normalize(&self) -> String {
+        let (name, email) = match self {
+            CargoAuthor::Name(author) => match author.find('<') {
+                Some(start) => (&author[..start], Some(author[start..].trim().trim_start_matches('<')
+                                                                            .trim_end_matches('>'))),
+                None if author.contains('@') && !author.trim().contains(char::is_whitespace) => {
+                    ("", Some(author.as_str()))
+                },
+                None => (author.as_str(), None),
+            },
+            CargoAuthor::Detailed { name, email } => (name.as_str(), email.as_deref()),
+        };
+        let name = name.split_whitespace().collect::<Vec<&str>>().join(" ");
+
+        match email.map(str::trim).filter(|email| !email.is_empty()) {
+            Some(email) if name.is_empty() => format!("<{}>", email),
+            Some(email) => format!("{} <{}>", name, email),
+            None => name,
+        }
+    }
+}
+
+/// an entry in `[dependencies]`, either a version string or a table
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum CargoDependency {
+    Version(String),
+    Detailed(CargoDependencyDetail),
+}
+
+/// the table form of a dependency
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CargoDependencyDetail {
+    pub version: Option<String>,
+    pub path: Option<String>,
+    pub optional: Option<bool>,
+    pub package: Option<String>,
+}
+
+impl CargoDependency {
+    /// The `path = "..."` of a local dependency
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            CargoDependency::Version(_) => None,
+            CargoDependency::Detailed(detail) => detail.path.as_deref(),
+        }
+    }
+
+    /// The crates.io name of the dependency declared as `key`, which may rename it
+    pub fn crate_name(&self, key: &str) -> String {
+        match self {
+            CargoDependency::Detailed(CargoDependencyDetail { package: Some(package), .. }) => package.clone(),
+            _ => key.to_string(),
+        }
+    }
+
+    /// Whether the dependency is only pulled in by a feature
+    pub fn is_optional(&self) -> bool {
+        match self {
+            CargoDependency::Version(_) => false,
+            CargoDependency::Detailed(detail) => detail.optional.unwrap_or(false),
+        }
+    }
+}
+
+/// data in `[workspace]` section
+#[derive(Clone, Debug, Deserialize)]
+pub struct CargoWorkspace {
+    #[serde(default)]
+    pub members: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// The directory holding Cargo.toml, from `--manifest-path`, `$CARGO_MANIFEST_DIR` or the current one
+pub fn manifest_dir(manifest_path: Option<&str>) -> PathBuf {
+    match manifest_path {
+        Some(val) => PathBuf::from(val),
+        None => match std::env::var("CARGO_MANIFEST_DIR") {
+            Ok(val) => PathBuf::from(val),
+            Err(_) => PathBuf::from("."),
+        }
+    }
+}
+
+/// cargo's target directory on this machine for the crate in `manifest_dir`, relative to it unless absolute,
+/// `None` for the default `target`. This is for finding what a local build left, never for the PKGBUILD.
+///
+/// Like cargo, `CARGO_TARGET_DIR` comes first, then `build.target-dir` from the `.cargo/config.toml`
+/// closest to the crate, and `$CARGO_HOME/config.toml` last.
+/// A `--target-dir` on the command line overrides all of them.
+pub fn cargo_target_dir(manifest_dir: &Path) -> Option<String> {
+    if let Some(dir) = std::env::var("CARGO_TARGET_DIR").ok().filter(|dir| !dir.is_empty()) {
+        return Some(dir);
+    }
+
+    let manifest_dir = manifest_dir.canonicalize().ok()?;
+    for (levels, dir) in manifest_dir.ancestors().enumerate() {
+        if let Some(target_dir) = config_target_dir(&dir.join(".cargo")) {
+            // relative paths are relative to the directory holding `.cargo`
+            return Some(match Path::new(&target_dir).is_absolute() {
+                true => target_dir,
+                false => "../".repeat(levels) + &target_dir,
+            });
+        }
+    }
+
+    let cargo_home = std::env::var_os("CARGO_HOME")
+                        .map(PathBuf::from)
+                        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))?;
+    config_target_dir(&cargo_home).map(|target_dir| {
+        cargo_home.parent().unwrap_or(&cargo_home).join(target_dir).to_string_lossy().into_owned()
+    })
+}
+
+/// cargo's target directory relative to the crate in `manifest_dir` the PKGBUILD falls back to
+/// when the build environment doesn't set `CARGO_TARGET_DIR`.
+///
+/// Only what ships with the project counts, a relative `build.target-dir` from the `.cargo/config.toml`
+/// closest to the crate up to its project root, otherwise `target` in the project root,
+/// where cargo builds every member of a workspace.
+/// This machine's `CARGO_TARGET_DIR`, `$CARGO_HOME` and absolute paths would break the build anywhere else.
+pub fn pkgbuild_target_dir(manifest_dir: &Path) -> String {
+    if manifest_dir.as_os_str().is_empty() {
+        return "target".to_string();
+    }
+
+    let dir = manifest_dir.canonicalize().unwrap_or_else(|_| manifest_dir.to_path_buf());
+    let root = project_root(&dir);
+    for (levels, ancestor) in dir.ancestors().enumerate() {
+        if let Some(target_dir) = config_target_dir(&ancestor.join(".cargo")) {
+            if Path::new(&target_dir).is_absolute() {
+                log::warn!("build.target-dir `{}` of {} is a path on this machine, the PKGBUILD uses `target`",
+                           target_dir, ancestor.join(".cargo").display());
+                break;
+            }
+            return "../".repeat(levels) + &target_dir;
+        }
+        if ancestor == root {
+            break;
+        }
+    }
+
+    "../".repeat(dir.ancestors().take_while(|ancestor| *ancestor != root).count()) + "target"
+}
+
+/// The closest workspace root above the crate in `dir`, or the crate itself outside of a workspace
+fn project_root(dir: &Path) -> PathBuf {
+    dir.ancestors()
+       .find(|ancestor| workspace_section(&ancestor.join("Cargo.toml")).is_some())
+       .unwrap_or(dir)
+       .to_path_buf()
+}
+
+/// `build.target-dir` of `config.toml`, or the older `config`, in the cargo config directory `dir`
+fn config_target_dir(dir: &Path) -> Option<String> {
+    ["config.toml", "config"].iter()
+                             .map(|name| dir.join(name))
+                             .filter_map(|path| std::fs::read_to_string(path).ok())
+                             .filter_map(|content| content.parse::<toml::Value>().ok())
+                             .find_map(|config| {
+                                 config.get("build")?.get("target-dir")?.as_str().map(str::to_string)
+                             })
+}
+
+/// Replace the `[package]` keys inherited with `key.workspace = true`, like `version.workspace = true`,
+/// by the value in `[workspace.package]` of the closest workspace root above `dir`.
+/// Inherited keys the workspace doesn't set are left for decoding to reject.
+fn inherit_workspace_fields(manifest: &mut toml::Value, dir: &Path) {
+    let package = match manifest.get_mut("package").and_then(|package| package.as_table_mut()) {
+        Some(package) => package,
+        None => return,
+    };
+    let inherited = package.iter()
+                           .filter(|(_, value)| value.get("workspace").and_then(|workspace| workspace.as_bool()) == Some(true))
+                           .map(|(key, _)| key.clone())
+                           .collect::<Vec<String>>();
+    if inherited.is_empty() {
+        return;
+    }
+
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let workspace_package = dir.ancestors()
+                               .map(|ancestor| ancestor.join("Cargo.toml"))
+                               .filter(|manifest| manifest.is_file())
+                               .find_map(|manifest| workspace_section(&manifest))
+                               .and_then(|workspace| workspace.get("package").cloned());
+
+    for key in inherited {
+        match workspace_package.as_ref().and_then(|workspace_package| workspace_package.get(&key)) {
+            Some(value) => {
+                package.insert(key, value.clone());
+            },
+            None => log::warn!("`{}.workspace = true` but no workspace above {} sets [workspace.package] {}",
+                               key, dir.display(), key),
+        }
+    }
+}
+
+thread_local! {
+    /// The `[workspace]` section of every manifest looked at, by canonical path,
+    /// so the members of a workspace share one read of its root
+    static WORKSPACE_SECTIONS: RefCell<HashMap<PathBuf, Option<toml::Value>>> = RefCell::new(HashMap::new());
+    /// Every manifest `workspace_section` read from disk
+    #[cfg(test)]
+    static WORKSPACE_READS: RefCell<Vec<PathBuf>> = const { RefCell::new(vec![]) };
+}
+
+/// The `[workspace]` section of `manifest`, read and parsed once per invocation
+pub fn workspace_section(manifest: &Path) -> Option<toml::Value> {
+    let manifest = manifest.canonicalize().unwrap_or_else(|_| manifest.to_path_buf());
+    WORKSPACE_SECTIONS.with(|sections| {
+        sections.borrow_mut()
+                .entry(manifest.clone())
+                .or_insert_with(|| {
+                    #[cfg(test)]
+                    WORKSPACE_READS.with(|reads| reads.borrow_mut().push(manifest.clone()));
+                    std::fs::read_to_string(&manifest).ok()
+                                                      .and_then(|content| content.parse::<toml::Value>().ok())
+                                                      .and_then(|root| root.get("workspace").cloned())
+                })
+                .clone()
+    })
+}
+
+/// How many times this thread's `workspace_section` read `manifest`
+#[cfg(test)]
+pub fn workspace_reads(manifest: &Path) -> usize {
+    let manifest = manifest.canonicalize().unwrap();
+    WORKSPACE_READS.with(|reads| reads.borrow().iter().filter(|read| **read == manifest).count())
+}
+
+impl Cargo {
+    /// Load the Cargo.toml inside `dir`, reading the arch metadata from `metadata_section`
+    pub fn from_dir(dir: &Path, metadata_section: Option<&str>) -> Cargo {
+        Cargo::load(dir, metadata_section).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// `from_dir`, with an unreadable or undecodable manifest as an error
+    pub fn load(dir: &Path, metadata_section: Option<&str>) -> Result<Cargo, String> {
+        let path = dir.join("Cargo.toml");
+        let content = std::fs::read_to_string(&path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+        let mut manifest = content.parse::<toml::Value>()
+                                  .map_err(|e| format!("could not decode {}: {}", path.display(), e))?;
+        inherit_workspace_fields(&mut manifest, dir);
+        let mut cargo = manifest.try_into::<Cargo>()
+                                .map_err(|e| format!("could not decode {}: {}", path.display(), e))?;
+        cargo.manifest_dir = dir.to_path_buf();
+        cargo.metadata_section = metadata_section.map(|section| section.to_string());
+        cargo.check_metadata()?;
+        Ok(cargo)
+    }
+
+    /// Parse a manifest that doesn't live on disk, it has no manifest directory to find files in
+    pub fn from_manifest_str(content: &str, metadata_section: Option<&str>) -> Cargo {
+        Cargo::parse(content, metadata_section).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// `from_manifest_str`, with an undecodable manifest as an error
+    pub fn parse(content: &str, metadata_section: Option<&str>) -> Result<Cargo, String> {
+        let mut cargo = toml::from_str::<Cargo>(content).map_err(|e| format!("could not decode the manifest: {}", e))?;
+        cargo.metadata_section = metadata_section.map(|section| section.to_string());
+        cargo.check_metadata()?;
+        Ok(cargo)
+    }
+
+    /// The metadata section must decode, `to_config` relies on it
+    fn check_metadata(&self) -> Result<(), String> {
+        let section = self.metadata_section.as_deref().unwrap_or("arch");
+        match self.package.metadata.as_ref().and_then(|metadata| metadata.decode_arch(section)) {
+            Some(Err(e)) => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// `path` in the manifest directory, if there is one
+    fn crate_file(&self, path: &str) -> Option<PathBuf> {
+        if self.manifest_dir.as_os_str().is_empty() {
+            None
+        } else {
+            Some(self.manifest_dir.join(path))
+        }
+    }
+
+    /// Whether the crate builds a binary, declared or by Cargo's conventions
+    pub fn has_bin(&self) -> bool {
+        self.bin.as_ref().map(|bin| !bin.is_empty()).unwrap_or(false)
+            || self.crate_file("src/main.rs").map(|file| file.is_file()).unwrap_or(false)
+            || self.crate_file("src/bin").map(|dir| dir.is_dir()).unwrap_or(false)
+    }
+
+    /// The names of the binaries the crate builds, declared or by Cargo's conventions
+    pub fn bin_names(&self) -> Vec<String> {
+        let mut names = self.bin.iter()
+                                .flatten()
+                                .filter_map(|bin| bin.name.clone())
+                                .collect::<Vec<String>>();
+
+        if self.crate_file("src/main.rs").map(|file| file.is_file()).unwrap_or(false) {
+            names.push(self.package.name.clone());
+        }
+        let mut auto_bins = self.crate_file("src/bin")
+            .and_then(|dir| std::fs::read_dir(dir).ok())
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+                let path = entry.path();
+                if path.extension().map(|extension| extension == "rs").unwrap_or(false) {
+                    path.file_stem().map(|stem| stem.to_string_lossy().into_owned())
+                } else if path.join("main.rs").is_file() {
+                    path.file_name().map(|name| name.to_string_lossy().into_owned())
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<String>>();
+        auto_bins.sort();
+        names.extend(auto_bins);
+
+        let mut seen = std::collections::HashSet::new();
+        names.retain(|name| seen.insert(name.clone()));
+        names
+    }
+
+    /// The name of the library target, the package name with `-` turned into `_` unless declared
+    pub fn lib_name(&self) -> Option<String> {
+        if !self.has_lib() {
+            return None;
+        }
+        self.lib.as_ref()
+                .and_then(|lib| lib.name.clone())
+                .or_else(|| Some(self.package.name.replace('-', "_")))
+    }
+
+    /// Whether the crate builds a library, declared or by Cargo's conventions
+    pub fn has_lib(&self) -> bool {
+        self.lib.is_some() || self.crate_file("src/lib.rs").map(|file| file.is_file()).unwrap_or(false)
+    }
+
+    /// Whether the crate has a build script, declared or by Cargo's conventions
+    pub fn has_build_script(&self) -> bool {
+        match &self.package.build {
+            Some(toml::Value::Boolean(build)) => *build,
+            Some(_) => true,
+            None => self.crate_file("build.rs").map(|file| file.is_file()).unwrap_or(false),
+        }
+    }
+
+    /// The crates in `[build-dependencies]`, by their crates.io name
+    pub fn build_dependencies(&self) -> Vec<String> {
+        self.build_dependencies.iter()
+                               .flatten()
+                               .map(|(name, dependency)| dependency.crate_name(name))
+                               .collect()
+    }
+
+    /// The crates in `[dependencies]`, by their crates.io name, optional ones included
+    pub fn all_dependencies(&self) -> Vec<String> {
+        self.dependencies.iter()
+                         .flatten()
+                         .map(|(name, dependency)| dependency.crate_name(name))
+                         .collect()
+    }
+
+    /// The crates in `[dependencies]` a build with `features` links, by their crates.io name:
+    /// the required ones and the optional ones the features turn on, see `enabled_features`
+    pub fn runtime_dependencies(&self, features: &[String], default_features: bool) -> Vec<String> {
+        let (_, enabled) = self.enabled_features(features, default_features);
+        self.dependencies.iter()
+                         .flatten()
+                         .filter(|(name, dependency)| !dependency.is_optional() || enabled.contains(*name))
+                         .map(|(name, dependency)| dependency.crate_name(name))
+                         .collect()
+    }
+
+    /// The features a build with `features`, and `default` when `default_features` is set, turns on,
+    /// and the optional dependencies among them, by their `[dependencies]` key
+    pub fn enabled_features(&self, features: &[String], default_features: bool) -> (HashSet<String>, HashSet<String>) {
+        let table = self.features.clone().unwrap_or_default();
+        let mut enabled = HashSet::new();
+        let mut seen = HashSet::new();
+        let mut pending = features.to_vec();
+        if default_features {
+            pending.push("default".to_string());
+        }
+
+        while let Some(entry) = pending.pop() {
+            let feature = if let Some(dependency) = entry.strip_prefix("dep:") {
+                enabled.insert(dependency.to_string());
+                continue;
+            } else if let Some((dependency, _)) = entry.split_once('/') {
+                // `foo?/bar` only forwards `bar` when `foo` is on anyway
+                match dependency.strip_suffix('?') {
+                    Some(_) => continue,
+                    None => dependency.to_string(),
+                }
+            } else {
+                entry
+            };
+            if !seen.insert(feature.clone()) {
+                continue;
+            }
+            match table.get(&feature) {
+                Some(entries) => pending.extend(entries.iter().cloned()),
+                // an optional dependency without `dep:` references is a feature of its own
+                None => {
+                    enabled.insert(feature);
+                },
+            }
+        }
+
+        (seen, enabled)
+    }
+}
+
 
 /// A trait for making specific platform package config's settings
 pub trait ToPackageConfig<T> {
@@ -33,5 +502,5 @@ pub trait ToPackageConfig<T> {
 
 /// A trait for generate specific platform package's config
 pub trait GeneratePackageConfig {
-    fn generate_package_config(&self);
+    fn generate_package_config(&self) -> Result<(), String>;
 }
@@ -0,0 +1,72 @@
+//! Shell-style globs over the files of a directory, for `backup_glob`.
+
+use std::fs;
+use std::path::Path;
+
+
+/// Whether the `/` separated relative `path` matches `pattern`:
+/// `*` and `?` stay within a path component, `**` spans any number of them.
+pub fn glob_matches(pattern: &str, path: &str) -> bool {
+    fn components(pattern: &[&str], path: &[&str]) -> bool {
+        match (pattern.first(), path.first()) {
+            (None, None) => true,
+            (Some(&"**"), _) => {
+                components(&pattern[1..], path) || (!path.is_empty() && components(pattern, &path[1..]))
+            },
+            (Some(component), Some(name)) => {
+                component_matches(&component.chars().collect::<Vec<char>>(), &name.chars().collect::<Vec<char>>())
+                    && components(&pattern[1..], &path[1..])
+            },
+            _ => false,
+        }
+    }
+
+    fn component_matches(pattern: &[char], name: &[char]) -> bool {
+        match (pattern.first(), name.first()) {
+            (None, None) => true,
+            (Some('*'), _) => {
+                component_matches(&pattern[1..], name) || (!name.is_empty() && component_matches(pattern, &name[1..]))
+            },
+            (Some('?'), Some(_)) => component_matches(&pattern[1..], &name[1..]),
+            (Some(p), Some(c)) if p == c => component_matches(&pattern[1..], &name[1..]),
+            _ => false,
+        }
+    }
+
+    let pattern = pattern.trim_start_matches('/').split('/').collect::<Vec<&str>>();
+    let path = path.split('/').collect::<Vec<&str>>();
+    components(&pattern, &path)
+}
+
+/// Directories `expand_glob` doesn't descend into, the build output and the repository,
+/// which can be huge when `dir` is the crate itself
+const SKIPPED_DIRS: &[&str] = &["target", ".git"];
+
+/// The files under `dir` matching `pattern`, as sorted `/` separated paths relative to it,
+/// leaving out `SKIPPED_DIRS`
+pub fn expand_glob(dir: &Path, pattern: &str) -> Vec<String> {
+    fn walk(dir: &Path, prefix: &str, files: &mut Vec<String>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+            let path = entry.path();
+            if path.is_dir() {
+                if !SKIPPED_DIRS.contains(&name.as_str()) {
+                    walk(&path, &relative, files);
+                }
+            } else {
+                files.push(relative);
+            }
+        }
+    }
+
+    let mut files = vec![];
+    walk(dir, "", &mut files);
+    files.retain(|file| glob_matches(pattern, file));
+    files.sort();
+    files
+}
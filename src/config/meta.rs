@@ -1,10 +1,156 @@
 //! Metadata for different platform's package
 
-use super::arch::CargoArch;
+use std::collections::BTreeMap;
+use std::fmt;
+
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use toml;
+
+use super::arch::{ALIASES, CargoArch};
 
 
 /// data in `[package.metadata]` section
 #[derive(Clone, Debug, Default, Deserialize)]
 pub struct CargoMetadata {
-    pub arch: Option<CargoArch>,
+    /// every `[package.metadata.<name>]` table, decoded on demand
+    #[serde(flatten)]
+    pub sections: BTreeMap<String, toml::Value>,
+}
+
+impl CargoMetadata {
+    /// Decode `[package.metadata.<name>]` as arch metadata, leaving out the `unknown_keys`
+    pub fn arch(&self, name: &str) -> Option<CargoArch> {
+        self.decode_arch(name).map(|arch| arch.unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    /// `arch`, with a section that doesn't decode, like a string where an array is expected, as an error
+    pub fn decode_arch(&self, name: &str) -> Option<Result<CargoArch, String>> {
+        self.sections.get(name).map(|section| {
+            let mut section = section.clone();
+            if let Some(table) = section.as_table_mut() {
+                let fields = struct_fields::<CargoArch>();
+                table.retain(|key, _| is_known(key, fields));
+            }
+
+            section.try_into::<CargoArch>()
+                   .map_err(|e| format!("could not decode [package.metadata.{}]: {}", name, e))
+        })
+    }
+
+    /// The keys of `[package.metadata.<name>]` that aren't arch metadata,
+    /// each with the closest known one as a suggestion
+    pub fn unknown_keys(&self, name: &str) -> Vec<(String, Option<&'static str>)> {
+        let fields = struct_fields::<CargoArch>();
+        self.sections.get(name)
+                     .and_then(|section| section.as_table())
+                     .into_iter()
+                     .flat_map(|table| table.keys())
+                     .filter(|key| !is_known(key, fields))
+                     .map(|key| (key.clone(), closest(key, fields)))
+                     .collect()
+    }
+
+    /// The aliases used in `[package.metadata.<name>]`, with the key each one stands for
+    pub fn aliases(&self, name: &str) -> Vec<(&'static str, &'static str)> {
+        let table = self.sections.get(name).and_then(|section| section.as_table());
+        ALIASES.iter()
+               .filter(|(alias, _)| table.map(|table| table.contains_key(*alias)).unwrap_or(false))
+               .copied()
+               .collect()
+    }
+}
+
+/// The keys of `[package.metadata.<name>]`, without the aliases
+pub fn metadata_keys() -> Vec<&'static str> {
+    struct_fields::<CargoArch>().iter()
+                                .copied()
+                                .filter(|field| !ALIASES.iter().any(|(alias, _)| alias == field))
+                                .collect()
+}
+
+/// Whether `key` is one of the `fields` or an alias
+fn is_known(key: &str, fields: &[&str]) -> bool {
+    fields.contains(&key) || ALIASES.iter().any(|(alias, _)| *alias == key)
+}
+
+/// The known key closest to a misspelled `key`, ignoring case, `-` and `_`,
+/// a key close to an alias gets the key the alias stands for
+fn closest(key: &str, fields: &[&'static str]) -> Option<&'static str> {
+    fn normalize(key: &str) -> Vec<char> {
+        key.chars().filter(|c| *c != '_' && *c != '-').flat_map(char::to_lowercase).collect()
+    }
+
+    let key = normalize(key);
+    fields.iter()
+          .map(|name| {
+              let field = ALIASES.iter().find(|(alias, _)| alias == name).map(|(_, field)| *field);
+              (*name, field.unwrap_or(name))
+          })
+          .map(|(name, field)| (distance(&key, &normalize(name)), field))
+          .filter(|(distance, _)| *distance <= 2.max(key.len() / 3))
+          .min_by_key(|(distance, _)| *distance)
+          .map(|(_, field)| field)
+}
+
+/// Levenshtein distance
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The keys the derived `Deserialize` of the struct `T` knows, the aliases included
+fn struct_fields<T: for<'de> Deserialize<'de>>() -> &'static [&'static str] {
+    /// Records the field list serde hands to `deserialize_struct`, then stops
+    struct FieldsDeserializer<'a>(&'a mut &'static [&'static str]);
+
+    #[derive(Debug)]
+    struct Stop;
+
+    impl fmt::Display for Stop {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "stop")
+        }
+    }
+
+    impl std::error::Error for Stop {}
+
+    impl de::Error for Stop {
+        fn custom<M: fmt::Display>(_: M) -> Stop {
+            Stop
+        }
+    }
+
+    impl<'de, 'a> Deserializer<'de> for FieldsDeserializer<'a> {
+        type Error = Stop;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Stop> {
+            Err(Stop)
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(self,
+                                               _: &'static str,
+                                               fields: &'static [&'static str],
+                                               _: V) -> Result<V::Value, Stop> {
+            *self.0 = fields;
+            Err(Stop)
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(FieldsDeserializer(&mut fields));
+    fields
 }
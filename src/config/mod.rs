@@ -3,7 +3,15 @@
 pub mod core;
 pub mod meta;
 pub mod arch;
+pub mod validate;
+pub mod template;
+pub mod workspace;
+pub mod glob;
 
 pub use self::core::*;
 pub use self::meta::*;
 pub use self::arch::*;
+pub use self::validate::*;
+pub use self::template::*;
+pub use self::workspace::*;
+pub use self::glob::*;
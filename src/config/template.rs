@@ -0,0 +1,63 @@
+//! Embedded PKGBUILD templates holding the build functions.
+
+use super::core::Cargo;
+
+
+/// All embedded templates by name
+pub const TEMPLATES: &[(&str, &str)] = &[
+    ("default", include_str!("PKGBUILD-TEMPLATE")),
+    ("bin", include_str!("PKGBUILD-TEMPLATE-bin")),
+    ("lib", include_str!("PKGBUILD-TEMPLATE-lib")),
+    ("git", include_str!("PKGBUILD-TEMPLATE-git")),
+    ("cargo-install", include_str!("PKGBUILD-TEMPLATE-cargo-install")),
+];
+
+/// Look up an embedded template by name
+pub fn template(name: &str) -> Option<&'static str> {
+    TEMPLATES.iter()
+             .find(|(template_name, _)| *template_name == name)
+             .map(|(_, content)| *content)
+}
+
+/// Pick the template fitting the crate:
+/// `git` for VCS packages, `bin` when there is a binary target,
+/// `lib` for library-only crates and `default` when nothing is detected.
+pub fn detect_template(cargo: &Cargo, vcs: bool) -> &'static str {
+    if vcs {
+        "git"
+    } else if cargo.has_bin() {
+        "bin"
+    } else if cargo.has_lib() {
+        "lib"
+    } else {
+        "default"
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ToPackageConfig;
+    use crate::testing::{TempDir, manifest};
+
+    /// The template picked for a crate with `files`, and `metadata` as its `[package.metadata.arch]`
+    fn template_name(files: &[&str], metadata: &str) -> String {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", metadata));
+        for file in files {
+            dir.write(file, "");
+        }
+        Cargo::from_dir(dir.path(), None).to_config().template_name
+    }
+
+    #[test]
+    fn template_fits_the_crate() {
+        assert_eq!(template_name(&["src/lib.rs"], ""), "lib");
+        assert_eq!(template_name(&["src/main.rs"], ""), "bin");
+        assert_eq!(template_name(&["src/lib.rs", "src/bin/foo.rs"], ""), "bin");
+        assert_eq!(template_name(&[], ""), "default");
+        assert_eq!(template_name(&["src/main.rs"], "vcs = true\n"), "git");
+    }
+
+}
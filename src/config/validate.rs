@@ -0,0 +1,658 @@
+//! Sanity checks for the resolved package config.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::arch::{ArchConfig, FieldSource, expand_source, is_remote, is_vcs, relation_name, source_filename};
+use super::template::{template, TEMPLATES};
+
+
+/// How serious a problem found by `validate` is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in the resolved package config
+#[derive(Clone, Debug, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// The PKGBUILD field the problem is about
+    pub field: &'static str,
+    /// The check that found it, stable across releases like `empty-maintainers`
+    pub check: &'static str,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn warning(field: &'static str, check: &'static str, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, field, check, message }
+    }
+
+    pub fn error(field: &'static str, check: &'static str, message: String) -> Diagnostic {
+        Diagnostic { severity: Severity::Error, field, check, message }
+    }
+}
+
+/// Collects the warnings and errors found while resolving and validating a config
+#[derive(Clone, Debug, Default, Serialize)]
+#[serde(transparent)]
+pub struct Diagnostics {
+    items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn warning(&mut self, field: &'static str, check: &'static str, message: String) {
+        self.items.push(Diagnostic::warning(field, check, message));
+    }
+
+    pub fn error(&mut self, field: &'static str, check: &'static str, message: String) {
+        self.items.push(Diagnostic::error(field, check, message));
+    }
+
+    pub fn extend(&mut self, other: &Diagnostics) {
+        self.items.extend(other.items.iter().cloned());
+    }
+
+    /// `--strict`, turn every warning into an error
+    pub fn promote_warnings(&mut self) {
+        for diagnostic in &mut self.items {
+            diagnostic.severity = Severity::Error;
+        }
+    }
+
+    /// `--lenient`, turn the errors about `field` into warnings
+    pub fn demote_errors(&mut self, field: &str) {
+        for diagnostic in self.items.iter_mut().filter(|diagnostic| diagnostic.field == field) {
+            diagnostic.severity = Severity::Warning;
+        }
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(|d| d.severity == Severity::Error)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.items.iter()
+    }
+
+    /// `validate --format sarif`, a SARIF 2.1.0 log with one rule per check, `arch/<check>`,
+    /// every result located in `manifest`
+    pub fn to_sarif(&self, manifest: &Path) -> serde_json::Value {
+        fn rule_id(check: &str) -> String {
+            format!("arch/{}", check)
+        }
+
+        let mut rules = self.items.iter().map(|diagnostic| rule_id(diagnostic.check)).collect::<Vec<String>>();
+        rules.sort();
+        rules.dedup();
+
+        let results = self.items.iter().map(|diagnostic| serde_json::json!({
+            "ruleId": rule_id(diagnostic.check),
+            "level": diagnostic.severity.to_string(),
+            "message": { "text": diagnostic.message },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": { "uri": manifest.display().to_string() },
+                },
+            }],
+        })).collect::<Vec<serde_json::Value>>();
+
+        serde_json::json!({
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "version": "2.1.0",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "cargo-arch",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules.iter().map(|id| serde_json::json!({ "id": id })).collect::<Vec<serde_json::Value>>(),
+                    },
+                },
+                "results": results,
+            }],
+        })
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "cargo-arch: {}: {}: {}", self.severity, self.field, self.message)
+    }
+}
+
+
+impl ArchConfig {
+    /// Check the resolved config for mistakes makepkg would choke on,
+    /// on top of the ones found while resolving it
+    pub fn validate(&self) -> Diagnostics {
+        let mut diagnostics = self.diagnostics.clone();
+
+        check_mandatory(self, &mut diagnostics);
+        check_maintainers(&self.maintainers, &mut diagnostics);
+        if self.provenance().get("pkgver") == Some(&FieldSource::Metadata) {
+            check_pkgver(&self.pkgver, &mut diagnostics);
+        }
+        check_pkgdesc(&self.pkgdesc, self.pkgdesc_max_length, &mut diagnostics);
+        check_template_name(&self.template_name, &mut diagnostics);
+        check_depends_conflicts(&self.depends, &self.conflicts, &mut diagnostics);
+        check_soname_depends(&self.depends, &mut diagnostics);
+        check_groups(&self.groups, &mut diagnostics);
+        check_multilib(self, &mut diagnostics);
+        let source = self.source.iter()
+                                .map(|source| expand_source(source, &self.pkgname, &self.pkgver))
+                                .collect::<Vec<String>>();
+        check_source_schemes(&source, &mut diagnostics);
+        check_local_sources(&source, &self.manifest_dir, &mut diagnostics);
+        check_vcs_checksums(&self.source,
+                            &[("md5sums", &self.md5sums), ("sha1sums", &self.sha1sums),
+                              ("sha224sums", &self.sha224sums), ("sha256sums", &self.sha256sums),
+                              ("sha384sums", &self.sha384sums), ("sha512sums", &self.sha512sums),
+                              ("b2sums", &self.b2sums)],
+                            &mut diagnostics);
+        if self.locked {
+            check_lockfile(&self.manifest_dir, self.vcs, &mut diagnostics);
+        }
+
+        diagnostics
+    }
+}
+
+/// License names from before Arch Linux switched to SPDX identifiers, namcap flags them
+const LEGACY_LICENSES: &[&str] = &[
+    "AGPL", "AGPL3", "APACHE", "Apache", "Artistic2.0", "BSD", "CCPL", "CDDL", "CPL", "EPL", "FDL", "FDL1.2", "FDL1.3",
+    "GPL", "GPL2", "GPL3", "LGPL", "LGPL2.1", "LGPL3", "LPPL", "MPL", "MPL2", "PerlArtistic", "PHP", "PSF",
+    "RUBY", "W3C", "ZPL",
+];
+
+impl ArchConfig {
+    /// `validate --namcap-clean`, what namcap complains about in a PKGBUILD passing `validate`:
+    /// no `url`, no description or one starting with the package name, and licenses that aren't SPDX
+    pub fn check_namcap(&self, diagnostics: &mut Diagnostics) {
+        if self.url.trim().is_empty() {
+            diagnostics.warning("url", "namcap-empty-url", "is empty, set `url` or the package homepage or repository".to_string());
+        }
+
+        if self.pkgdesc.trim().is_empty() {
+            diagnostics.warning("pkgdesc", "namcap-empty-pkgdesc", "is empty, set `pkgdesc` or the package description".to_string());
+        } else if self.pkgdesc.to_lowercase()
+                              .strip_prefix(&self.pkgname.to_lowercase())
+                              .map(|rest| !rest.starts_with(|c: char| c.is_alphanumeric()))
+                              .unwrap_or(false) {
+            diagnostics.warning("pkgdesc", "namcap-pkgdesc-pkgname", format!("starts with the package name `{}`, drop it", self.pkgname));
+        }
+
+        if self.license.iter().all(|license| license.trim().is_empty()) {
+            diagnostics.warning("license", "namcap-empty-license", "is empty, set `license` or the package license".to_string());
+        }
+        for license in self.license.iter().filter(|license| !license.trim().is_empty()) {
+            if LEGACY_LICENSES.contains(&license.trim()) {
+                diagnostics.warning(
+                    "license", "namcap-legacy-license",
+                    format!("`{}` is an old Arch Linux license name, use its SPDX identifier like `GPL-3.0-or-later`",
+                            license));
+            } else if !license.chars().all(|c| c.is_ascii_alphanumeric() || " .+-:()".contains(c)) {
+                diagnostics.warning("license", "namcap-non-spdx-license", format!("`{}` is not an SPDX license expression", license));
+            }
+        }
+    }
+}
+
+/// A release number makepkg accepts, like `2` or `2.1`,
+/// whether it comes from `pkgrel`, `default_pkgrel`, `CARGO_ARCH_PKGREL` or `--pkgrel`
+pub fn is_pkgrel(pkgrel: &str) -> bool {
+    pkgrel.splitn(2, '.').all(|release| !release.is_empty() && release.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// makepkg refuses a PKGBUILD with an empty mandatory field, with a message naming neither the crate nor the fix
+fn check_mandatory(config: &ArchConfig, diagnostics: &mut Diagnostics) {
+    if config.pkgname.trim().is_empty() {
+        diagnostics.error("pkgname", "empty-pkgname", "is empty, set `pkgname` or the package name".to_string());
+    }
+    if config.pkgver.trim().is_empty() {
+        diagnostics.error("pkgver", "empty-pkgver", "is empty, set `pkgver` or the package version".to_string());
+    }
+    if config.pkgrel.trim().is_empty() {
+        diagnostics.error("pkgrel", "empty-pkgrel", "is empty, set `pkgrel` or leave it out for `1`".to_string());
+    } else if !is_pkgrel(&config.pkgrel) {
+        diagnostics.error("pkgrel", "invalid-pkgrel", format!("`{}` is not a release number like `2` or `2.1`", config.pkgrel));
+    }
+    if config.arch.iter().all(|arch| arch.trim().is_empty()) {
+        diagnostics.error("arch", "empty-arch", "is empty, set `arch`, e.g. `[\"x86_64\"]`, or `[\"any\"]` for architecture independent packages".to_string());
+    }
+}
+
+/// Nobody to contact about the package
+fn check_maintainers(maintainers: &[String], diagnostics: &mut Diagnostics) {
+    if maintainers.iter().all(|maintainer| maintainer.trim().is_empty()) {
+        diagnostics.warning(
+            "maintainers", "empty-maintainers",
+            "no maintainer, the PKGBUILD will have no `# Maintainer:` line".to_string());
+    }
+}
+
+/// The AUR guidelines want a short description without a trailing period
+fn check_pkgdesc(pkgdesc: &str, max_length: usize, diagnostics: &mut Diagnostics) {
+    let length = pkgdesc.chars().count();
+    if length > max_length {
+        diagnostics.warning(
+            "pkgdesc", "pkgdesc-too-long",
+            format!("{} characters long, keep it under {}", length, max_length));
+    }
+
+    if pkgdesc.ends_with('.') {
+        diagnostics.warning(
+            "pkgdesc", "pkgdesc-period",
+            "ends with a period, AUR descriptions don't".to_string());
+    }
+}
+
+/// A `pkgver` set in the metadata must not carry the epoch (`1:`) or the pkgrel (`-3`),
+/// this runs on the raw value since sanitizing turns `-` into `_`.
+/// The crate version is left alone, `1.0.0-2` is a valid pre-release there.
+fn check_pkgver(pkgver: &str, diagnostics: &mut Diagnostics) {
+    if let Some((epoch, version)) = pkgver.split_once(':') {
+        diagnostics.error(
+            "pkgver", "pkgver-epoch",
+            format!("`{}` contains an epoch, use `epoch = \"{}\"` and `pkgver = \"{}\"` instead",
+                    pkgver, epoch, version));
+    }
+
+    // a `-` in the build metadata, like `+build-5`, is no pkgrel
+    let (release, _) = pkgver.split_once('+').unwrap_or((pkgver, ""));
+    if let Some((version, pkgrel)) = release.rsplit_once('-') {
+        if !pkgrel.is_empty() && pkgrel.chars().all(|c| c.is_ascii_digit()) {
+            diagnostics.error(
+                "pkgver", "pkgver-pkgrel",
+                format!("`{}` ends with something like a pkgrel, use `pkgver = \"{}\"` and `pkgrel = \"{}\"` instead",
+                        pkgver, version, pkgrel));
+        }
+    }
+}
+
+/// The template must be one of the embedded ones
+fn check_template_name(template_name: &str, diagnostics: &mut Diagnostics) {
+    if template(template_name).is_none() {
+        let names = TEMPLATES.iter().map(|(name, _)| format!("`{}`", name)).collect::<Vec<String>>();
+        diagnostics.error(
+            "template_name", "unknown-template",
+            format!("unknown template `{}`, expected one of {}", template_name, names.join(", ")));
+    }
+}
+
+/// A package can't conflict with what it depends on
+fn check_depends_conflicts(depends: &[String], conflicts: &[String], diagnostics: &mut Diagnostics) {
+    for depend in depends.iter().filter(|depend| conflicts.contains(depend)) {
+        diagnostics.warning(
+            "conflicts", "depends-conflicts",
+            format!("`{}` is in both depends and conflicts", depend));
+    }
+}
+
+/// makepkg resolves `libfoo.so` depends against the sonames packages provide,
+/// they're passed through but a name without the `lib` prefix never matches one
+fn check_soname_depends(depends: &[String], diagnostics: &mut Diagnostics) {
+    for depend in depends {
+        let name = relation_name(depend);
+        let valid = name.strip_suffix(".so")
+                        .map(|stem| stem.strip_prefix("lib").map(|lib| !lib.is_empty()).unwrap_or(false));
+        if valid == Some(false) {
+            diagnostics.warning(
+                "depends", "soname-depends",
+                format!("`{}` is not a soname like `libfoo.so`, it won't match any package's provides", depend));
+        }
+    }
+}
+
+/// Group names follow the pkgname rules: lowercase alphanumerics and `@._+-`,
+/// not starting with `-` or `.`
+fn check_groups(groups: &[String], diagnostics: &mut Diagnostics) {
+    let valid_char = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "@._+-".contains(c);
+
+    for group in groups {
+        if !group.is_empty() && group.chars().all(valid_char) && !group.starts_with(['-', '.']) {
+            continue;
+        }
+        let normalized = group.trim()
+                              .to_lowercase()
+                              .split(|c: char| c.is_whitespace() || !valid_char(c))
+                              .filter(|part| !part.is_empty())
+                              .collect::<Vec<&str>>()
+                              .join("-");
+        let normalized = normalized.trim_start_matches(['-', '.']);
+        match normalized.is_empty() {
+            true => diagnostics.warning(
+                "groups", "invalid-group",
+                format!("`{}` is not a valid group name, use lowercase letters, digits and `@._+-`", group)),
+            false => diagnostics.warning(
+                "groups", "invalid-group",
+                format!("`{}` is not a valid group name, like a pkgname it should be `{}`", group, normalized)),
+        }
+    }
+}
+
+/// `lib32-` packages only exist in the x86_64 multilib repository
+fn check_multilib(config: &ArchConfig, diagnostics: &mut Diagnostics) {
+    if !config.multilib_libs.is_empty() && config.arch != ["x86_64"] {
+        diagnostics.warning(
+            "multilib", "multilib-arch",
+            format!("only applies to `arch = [\"x86_64\"]`, not ({}), depends are written without `lib32-`",
+                    config.arch.join(" ")));
+    }
+}
+
+/// The `Cargo.lock` used for `manifest_dir`, it sits at the workspace root for members
+fn find_lockfile(manifest_dir: &Path) -> Option<PathBuf> {
+    let manifest_dir = manifest_dir.canonicalize().unwrap_or_else(|_| manifest_dir.to_path_buf());
+    manifest_dir.ancestors()
+                .map(|dir| dir.join("Cargo.lock"))
+                .find(|lockfile| lockfile.is_file())
+}
+
+/// `--locked`, there must be a `Cargo.lock`, and VCS packages build from the repository so it must be committed
+fn check_lockfile(manifest_dir: &Path, vcs: bool, diagnostics: &mut Diagnostics) {
+    let lockfile = match find_lockfile(manifest_dir) {
+        Some(lockfile) => lockfile,
+        None => {
+            diagnostics.error(
+                "locked", "missing-lockfile",
+                format!("no Cargo.lock for {}, run `cargo generate-lockfile`", manifest_dir.display()));
+            return;
+        },
+    };
+
+    if vcs {
+        let tracked = Command::new("git")
+                              .args(["ls-files", "--error-unmatch", "Cargo.lock"])
+                              .current_dir(lockfile.parent().unwrap())
+                              .output()
+                              .map(|output| output.status.success())
+                              .unwrap_or(false);
+        if !tracked {
+            diagnostics.error(
+                "locked", "uncommitted-lockfile",
+                format!("{} is not committed, the package source won't include it", lockfile.display()));
+        }
+    }
+}
+
+/// makepkg only downloads these, a typo like `htps://` fails confusingly there
+fn check_source_schemes(source: &[String], diagnostics: &mut Diagnostics) {
+    for entry in source {
+        let url = entry.split_once("::").map(|(_, url)| url).unwrap_or(entry);
+        let scheme = match url.split_once(':') {
+            Some((scheme, _)) if !scheme.is_empty()
+                                 && scheme.chars().all(|c| c.is_ascii_alphanumeric() || "+.-".contains(c)) => scheme,
+            // no scheme, a local file
+            _ => continue,
+        };
+        let supported = match scheme.split_once('+') {
+            Some((vcs, protocol)) => ["git", "hg", "bzr", "svn", "fossil"].contains(&vcs) && !protocol.is_empty(),
+            None => ["http", "https", "ftp"].contains(&scheme),
+        };
+        if !supported || !url.contains("://") {
+            diagnostics.error(
+                "source", "source-scheme",
+                format!("`{}` has no scheme makepkg supports, use http, https, ftp or git+, hg+, bzr+, svn+ URLs, or a local file name", entry));
+        }
+    }
+}
+
+/// Local sources are read next to Cargo.toml, they have to be there
+fn check_local_sources(source: &[String], manifest_dir: &Path, diagnostics: &mut Diagnostics) {
+    for entry in source.iter().filter(|entry| !is_remote(entry)) {
+        if manifest_dir.as_os_str().is_empty() {
+            diagnostics.error(
+                "source", "local-source-no-manifest",
+                format!("local source `{}` can't be found without a manifest directory", entry));
+        } else if !manifest_dir.join(source_filename(entry)).exists() {
+            diagnostics.warning(
+                "source", "missing-local-source",
+                format!("local source `{}` not found in {}", entry, manifest_dir.display()));
+        }
+    }
+}
+
+/// makepkg can't verify a checkout, the checksum of a VCS source has to be `SKIP`
+fn check_vcs_checksums(source: &[String], checksums: &[(&str, &Vec<String>)], diagnostics: &mut Diagnostics) {
+    for (array, sums) in checksums {
+        for (entry, sum) in source.iter().zip(sums.iter()) {
+            if is_vcs(entry) && sum != "SKIP" {
+                diagnostics.warning(
+                    "source", "vcs-checksum",
+                    format!("`{}` is a VCS source, its entry in {} should be 'SKIP', not `{}`", entry, array, sum));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::arch::sanitize_pkgver;
+    use crate::testing::manifest;
+
+    fn diagnostics(metadata: &str) -> Diagnostics {
+        ArchConfig::from_manifest_str(&manifest("foo", metadata), None).validate()
+    }
+
+    /// The checks `validate` fails on the `pkgver` field
+    fn pkgver_checks(manifest: &str) -> Vec<&'static str> {
+        ArchConfig::from_manifest_str(manifest, None).validate()
+                                                     .iter()
+                                                     .filter(|diagnostic| diagnostic.field == "pkgver")
+                                                     .map(|diagnostic| diagnostic.check)
+                                                     .collect()
+    }
+
+    #[test]
+    fn pkgver_with_an_epoch_is_rejected() {
+        assert_eq!(pkgver_checks(&manifest("foo", "pkgver = \"1:2.0\"\n")), ["pkgver-epoch"]);
+    }
+
+    #[test]
+    fn pkgver_with_a_pkgrel_is_rejected() {
+        assert_eq!(pkgver_checks(&manifest("foo", "pkgver = \"2.0-3\"\n")), ["pkgver-pkgrel"]);
+        assert!(pkgver_checks(&manifest("foo", "pkgver = \"2.0+build-3\"\n")).is_empty());
+    }
+
+    #[test]
+    fn crate_pre_release_is_a_valid_pkgver() {
+        let manifest = manifest("foo", "").replace("version = \"1.0.0\"", "version = \"1.0.0-2\"");
+
+        assert!(pkgver_checks(&manifest).is_empty());
+        assert!(ArchConfig::from_manifest_str(&manifest, None).render_pkgbuild().contains("\npkgver=1.0.0_2\n"));
+    }
+
+    #[test]
+    fn sarif_has_a_rule_per_check() {
+        let diagnostics = diagnostics("arch = [\"x86_64\"]\n\
+                                       source = [\"htps://example.org/foo.tar.gz\", \"git+https://example.org/foo.git\"]\n\
+                                       sha256sums = [\"SKIP\", \"0123\"]\n");
+
+        let sarif = diagnostics.to_sarif(Path::new("foo/Cargo.toml"));
+        let sarif = serde_json::from_str::<serde_json::Value>(&serde_json::to_string(&sarif).unwrap()).unwrap();
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "cargo-arch");
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap()
+                                                   .iter()
+                                                   .map(|rule| rule["id"].as_str().unwrap())
+                                                   .collect::<Vec<&str>>();
+        assert_eq!(rules, ["arch/empty-maintainers", "arch/source-scheme", "arch/vcs-checksum"]);
+
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 3);
+        let maintainers = results.iter().find(|result| result["ruleId"] == "arch/empty-maintainers").unwrap();
+        assert_eq!(maintainers["level"], "warning");
+        assert!(maintainers["message"]["text"].as_str().unwrap().contains("no maintainer"));
+        assert_eq!(maintainers["locations"][0]["physicalLocation"]["artifactLocation"]["uri"], "foo/Cargo.toml");
+        let scheme = results.iter().find(|result| result["ruleId"] == "arch/source-scheme").unwrap();
+        assert_eq!(scheme["level"], "error");
+    }
+
+
+    /// The checks `validate` reports for `metadata`
+    fn checks(metadata: &str) -> Vec<&'static str> {
+        diagnostics(metadata).iter().map(|diagnostic| diagnostic.check).collect()
+    }
+
+    #[test]
+    fn pkgrel_must_be_a_release_number() {
+        let metadata = |pkgrel: &str| format!("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\n{}", pkgrel);
+
+        assert!(checks(&metadata("pkgrel = \"2.1\"\n")).is_empty());
+        assert_eq!(checks(&metadata("pkgrel = \"abc\"\n")), ["invalid-pkgrel"]);
+        assert_eq!(checks(&metadata("pkgrel = \"2.\"\n")), ["invalid-pkgrel"]);
+        assert_eq!(checks(&metadata("default_pkgrel = \"1-2\"\n")), ["invalid-pkgrel"]);
+    }
+
+    #[test]
+    fn long_pkgdesc_is_reported() {
+        let long = format!("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\npkgdesc = \"{}\"\n", "a".repeat(81));
+
+        assert_eq!(checks(&long), ["pkgdesc-too-long"]);
+        assert!(checks(&(long.clone() + "pkgdesc_max_length = 100\n")).is_empty());
+        assert!(checks(&format!("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\npkgdesc = \"{}\"\n", "é".repeat(80))).is_empty());
+    }
+
+
+    #[test]
+    fn soname_depends_need_the_lib_prefix() {
+        let depends = |depends: &str| format!("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\ndepends = [{}]\n", depends);
+
+        assert!(checks(&depends("\"libssl.so\", \"libz.so=1-64\", \"openssl\"")).is_empty());
+        assert_eq!(checks(&depends("\"ssl.so\"")), ["soname-depends"]);
+        assert_eq!(checks(&depends("\"lib.so>=1\"")), ["soname-depends"]);
+        assert!(diagnostics(&depends("\"ssl.so\"")).iter().next().unwrap().message.contains("`ssl.so` is not a soname like `libfoo.so`"));
+    }
+
+
+    #[test]
+    fn license_sharing_nothing_with_cargo_is_a_warning() {
+        let license = |license: &str| format!("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\nlicense = [{}]\n", license);
+
+        let mut mismatch = diagnostics(&license("\"GPL\""));
+        assert_eq!(mismatch.iter().map(|diagnostic| diagnostic.check).collect::<Vec<_>>(), ["license-mismatch"]);
+        let diagnostic = mismatch.iter().next().unwrap();
+        assert_eq!(diagnostic.severity, Severity::Warning);
+        assert_eq!(diagnostic.message, "(GPL) shares no license with Cargo's `MIT`, double-check it");
+        assert!(!mismatch.has_errors());
+        mismatch.promote_warnings();
+        assert!(mismatch.has_errors());
+
+        assert!(checks(&license("\"MIT\", \"GPL\"")).is_empty());
+    }
+
+
+    #[test]
+    fn empty_pkgver_is_rejected() {
+        assert_eq!(checks("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\npkgver = \"\"\n"), ["empty-pkgver"]);
+        assert_eq!(checks("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\npkgver = \" \"\n"), ["empty-pkgver"]);
+    }
+
+    #[test]
+    fn empty_arch_is_rejected() {
+        assert_eq!(checks("arch = []\nmaintainers = [\"Jane\"]\n"), ["empty-arch"]);
+        assert_eq!(checks("arch = [\"\"]\nmaintainers = [\"Jane\"]\n"), ["empty-arch"]);
+        let diagnostics = diagnostics("arch = []\nmaintainers = [\"Jane\"]\n");
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!((diagnostic.severity, diagnostic.field), (Severity::Error, "arch"));
+    }
+
+
+    #[test]
+    fn hashed_vcs_sources_are_reported() {
+        let source = "arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\n\
+                      source = [\"https://example.org/foo.tar.gz\", \"git+https://example.org/foo.git\"]\n";
+
+        assert!(checks(&(source.to_string() + "sha256sums = [\"0123\", \"SKIP\"]\n")).is_empty());
+        let diagnostics = diagnostics(&(source.to_string() + "sha256sums = [\"0123\", \"4567\"]\n"));
+        let messages = diagnostics.iter().map(|diagnostic| diagnostic.message.as_str()).collect::<Vec<&str>>();
+        assert_eq!(messages, ["`git+https://example.org/foo.git` is a VCS source, its entry in sha256sums should be 'SKIP', not `4567`"]);
+    }
+
+
+    #[test]
+    fn groups_follow_the_pkgname_rules() {
+        let groups = |groups: &str| format!("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\ngroups = [{}]\n", groups);
+
+        assert!(checks(&groups("\"base-devel\"")).is_empty());
+        let mut diagnostics = diagnostics(&groups("\"My Group\""));
+        let messages = diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect::<Vec<String>>();
+        assert_eq!(messages, ["`My Group` is not a valid group name, like a pkgname it should be `my-group`"]);
+        assert!(!diagnostics.has_errors());
+        diagnostics.promote_warnings();
+        assert!(diagnostics.has_errors());
+    }
+
+
+    #[test]
+    fn build_metadata_is_kept_in_the_pkgver() {
+        assert_eq!(sanitize_pkgver("1.2.3-rc.1+build.5"), "1.2.3_rc.1+build.5");
+
+        let with_metadata = manifest("foo", "").replace("version = \"1.0.0\"", "version = \"1.2.3-rc.1+build.5\"");
+        assert!(ArchConfig::from_manifest_str(&with_metadata, None).render_pkgbuild()
+                                                                   .contains("\npkgver=1.2.3_rc.1+build.5\n"));
+        assert!(pkgver_checks(&with_metadata).is_empty());
+
+        assert!(pkgver_checks(&manifest("foo", "pkgver = \"1.2.3+build-5\"\n")).is_empty());
+        assert_eq!(pkgver_checks(&manifest("foo", "pkgver = \"1.2.3-5+build.5\"\n")), ["pkgver-pkgrel"]);
+    }
+
+
+    #[test]
+    fn namcap_clean_reports_each_problem() {
+        let noncompliant = "[package]\nname = \"foo\"\nversion = \"1.0.0\"\ndescription = \"Foo: a tool\"\nlicense = \"\"\n\n\
+                            [package.metadata.arch]\nlicense = [\"GPL\", \"MIT/Apache\"]\n";
+        let config = ArchConfig::from_manifest_str(noncompliant, None);
+        let mut diagnostics = config.validate();
+        config.check_namcap(&mut diagnostics);
+
+        let mut checks = diagnostics.iter().map(|diagnostic| diagnostic.check).collect::<Vec<&str>>();
+        checks.sort_unstable();
+        assert_eq!(checks, ["empty-arch", "empty-maintainers", "namcap-empty-url", "namcap-legacy-license",
+                            "namcap-non-spdx-license", "namcap-pkgdesc-pkgname"]);
+
+        let config = ArchConfig::from_manifest_str(&manifest("foo", "").replace("license = \"MIT\"", "license = \"\""), None);
+        let mut diagnostics = Diagnostics::new();
+        config.check_namcap(&mut diagnostics);
+        assert!(diagnostics.iter().any(|diagnostic| diagnostic.check == "namcap-empty-license"));
+    }
+
+
+    #[test]
+    fn source_schemes_makepkg_supports() {
+        let source = |source: &str| format!("arch = [\"x86_64\"]\nmaintainers = [\"Jane\"]\nsource = [\"{}\"]\n", source);
+
+        assert!(checks(&source("https://example.org/foo-1.0.0.tar.gz")).is_empty());
+        assert!(checks(&source("foo.tar.gz::git+https://example.org/foo.git#tag=v1")).is_empty());
+
+        let diagnostics = diagnostics(&source("htps://example.org/foo-1.0.0.tar.gz"));
+        let diagnostic = diagnostics.iter().next().unwrap();
+        assert_eq!((diagnostic.severity, diagnostic.check), (Severity::Error, "source-scheme"));
+        assert!(diagnostic.message.starts_with("`htps://example.org/foo-1.0.0.tar.gz` has no scheme makepkg supports"));
+    }
+}
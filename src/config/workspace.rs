@@ -0,0 +1,289 @@
+//! Package every member of a Cargo workspace.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::arch::{ArchConfig, add_package};
+use super::core::{Cargo, CargoWorkspace, ToPackageConfig, workspace_section};
+
+
+/// The member directories listed in the workspace root's `[workspace]` section,
+/// `dir/*` globs are expanded to every sub-directory holding a Cargo.toml.
+pub fn workspace_members(root: &Path) -> Vec<PathBuf> {
+    let workspace = workspace_section(&root.join("Cargo.toml"))
+        .expect("no [workspace] section in manifest")
+        .try_into::<CargoWorkspace>()
+        .expect("could not decode workspace manifest");
+
+    let mut members = vec![];
+
+    for member in &workspace.members {
+        match member.strip_suffix("/*") {
+            Some(parent) => {
+                let mut dirs = fs::read_dir(root.join(parent))
+                    .expect("could not read workspace members")
+                    .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                    .filter(|dir| dir.join("Cargo.toml").is_file())
+                    .collect::<Vec<PathBuf>>();
+                dirs.sort();
+                members.extend(dirs);
+            },
+            None => members.push(root.join(member)),
+        }
+    }
+
+    members.retain(|dir| !workspace.exclude.iter().any(|exclude| *dir == root.join(exclude)));
+    members
+}
+
+/// Resolve the arch config of every workspace member, each one written into its own directory.
+///
+/// Path dependencies on other members are added to `depends` as the member's `pkgname`,
+/// when the member's `depends_from` derives `depends` from them.
+pub fn workspace_configs(root: &Path, metadata_section: Option<&str>) -> Vec<ArchConfig> {
+    member_configs(&workspace_cargos(root, metadata_section))
+}
+
+/// Resolve the arch config of the workspace member whose crate is `name`, for `--package`.
+///
+/// Errors when no member is named so or it has no `[package.metadata.<section>]`.
+pub fn workspace_member_config(root: &Path, metadata_section: Option<&str>, name: &str) -> Result<ArchConfig, String> {
+    let cargos = workspace_cargos(root, metadata_section);
+    let cargo = match cargos.iter().find(|cargo| cargo.package.name == name) {
+        Some(cargo) => cargo,
+        None => {
+            let names = cargos.iter().map(|cargo| cargo.package.name.as_str()).collect::<Vec<&str>>();
+            return Err(format!("no workspace member named `{}`, the members are: {}", name, names.join(", ")));
+        },
+    };
+
+    let section = metadata_section.unwrap_or("arch");
+    if !cargo.package.metadata.as_ref().is_some_and(|metadata| metadata.sections.contains_key(section)) {
+        return Err(format!("workspace member `{}` has no [package.metadata.{}] section", name, section));
+    }
+
+    Ok(member_configs(&cargos).into_iter()
+                              .find(|config| config.crate_name == name)
+                              .expect("the member was resolved"))
+}
+
+fn workspace_cargos(root: &Path, metadata_section: Option<&str>) -> Vec<Cargo> {
+    workspace_members(root).iter()
+                           .map(|dir| Cargo::from_dir(dir, metadata_section))
+                           .collect()
+}
+
+fn member_configs(cargos: &[Cargo]) -> Vec<ArchConfig> {
+    let mut configs = cargos.iter()
+                            .map(|cargo| cargo.to_config())
+                            .collect::<Vec<ArchConfig>>();
+
+    let pkgnames = cargos.iter()
+                         .zip(configs.iter())
+                         .map(|(cargo, config)| (canonical(&cargo.manifest_dir), config.pkgname.clone()))
+                         .collect::<HashMap<PathBuf, String>>();
+
+    for (cargo, config) in cargos.iter().zip(configs.iter_mut()) {
+        config.output_dir = cargo.manifest_dir.clone();
+
+        let crate_dependencies = &config.crate_dependencies;
+        let paths = cargo.dependencies.iter()
+                                      .flatten()
+                                      .filter(|(name, dependency)| crate_dependencies.contains(&dependency.crate_name(name)))
+                                      .filter_map(|(_, dependency)| dependency.path())
+                                      .collect::<Vec<&str>>();
+        for path in paths {
+            if let Some(pkgname) = pkgnames.get(&canonical(&cargo.manifest_dir.join(path))) {
+                if !config.depends.contains(pkgname) {
+                    config.depends.push(pkgname.clone());
+                }
+            }
+        }
+    }
+
+    for i in 0..configs.len() {
+        if !configs[i].detect_bin_conflicts {
+            continue;
+        }
+        let pkgname = configs[i].pkgname.clone();
+        let bins = configs[i].bins.clone();
+        for sibling in configs.iter_mut().filter(|sibling| sibling.pkgname != pkgname) {
+            if sibling.bins.iter().any(|bin| bins.contains(bin)) {
+                add_package(&mut sibling.conflicts, &pkgname, &sibling.pkgname.clone());
+            }
+        }
+    }
+
+    configs
+}
+
+fn canonical(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::config::core::workspace_reads;
+    use crate::testing::{TempDir, manifest};
+
+    /// A workspace with an `app` binary member depending on a `util` library member
+    fn workspace() -> TempDir {
+        policy_workspace("runtime-features")
+    }
+
+    /// `workspace` with the `depends_from` policy of `app`, which also has an optional path dependency on `extra`
+    fn policy_workspace(policy: &str) -> TempDir {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", "[workspace]\nmembers = [\"app\", \"util\", \"extra\"]\n");
+        dir.write("app/Cargo.toml", &(manifest("app", &format!("arch = [\"x86_64\"]\ndepends_from = \"{}\"\n", policy))
+                                      + "\n[dependencies]\nutil = { path = \"../util\" }\n\
+                                         extra = { path = \"../extra\", optional = true }\n"));
+        dir.write("extra/Cargo.toml", &manifest("extra", ""));
+        dir.write("extra/src/lib.rs", "");
+        dir.write("app/src/main.rs", "fn main() {\n    util::hello();\n}\n");
+        dir.write("util/Cargo.toml", &manifest("util", "pkgname = \"libutil\"\n"));
+        dir.write("util/src/lib.rs", "pub fn hello() {}\n");
+        dir
+    }
+
+    #[test]
+    fn members_depend_on_member_packages() {
+        let dir = workspace();
+
+        let configs = workspace_configs(dir.path(), None);
+
+        let app = configs.iter().find(|config| config.pkgname == "app").unwrap();
+        assert!(app.depends.contains(&"libutil".to_string()));
+        assert_eq!(app.output_dir, dir.path().join("app"));
+    }
+
+    #[test]
+    fn member_packages_from_the_workspace_target_dir() {
+        let dir = workspace();
+        let app = workspace_configs(dir.path(), None).into_iter()
+                                                     .find(|config| config.pkgname == "app")
+                                                     .unwrap();
+        let pkgbuild = dir.write("app/PKGBUILD", &app.render_pkgbuild());
+        assert!(app.render_pkgbuild().contains("\"${CARGO_TARGET_DIR:-../target}/release/app\""));
+
+        // what makepkg does, without the checks of the build environment
+        let output = Command::new("bash")
+                             .arg("-ec")
+                             .arg("source \"$1\"; cd \"$srcdir\"; build; cd \"$srcdir\"; package")
+                             .arg("makepkg")
+                             .arg(&pkgbuild)
+                             .env("srcdir", dir.path().join("app/src"))
+                             .env("pkgdir", dir.path().join("pkg"))
+                             .env("CARGO_NET_OFFLINE", "true")
+                             .env_remove("CARGO_TARGET_DIR")
+                             .env_remove("CARGO_BUILD_TARGET_DIR")
+                             .output()
+                             .unwrap();
+
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        assert!(dir.path().join("target/release/app").is_file());
+        assert!(dir.path().join("pkg/usr/bin/app").is_file());
+    }
+
+    fn app_depends(policy: &str) -> Vec<String> {
+        let dir = policy_workspace(policy);
+        workspace_configs(dir.path(), None).into_iter()
+                                           .find(|config| config.pkgname == "app")
+                                           .unwrap()
+                                           .depends
+    }
+
+    #[test]
+    fn member_depends_follow_depends_from() {
+        assert!(app_depends("none").is_empty());
+        assert!(app_depends("explicit").is_empty());
+        assert_eq!(app_depends("all-deps"), ["extra", "libutil"]);
+        assert_eq!(app_depends("runtime-features"), ["libutil"]);
+    }
+
+    #[test]
+    fn workspace_manifest_is_read_once() {
+        let dir = TempDir::new();
+        let members = (0..10).map(|i| format!("\"member{}\"", i)).collect::<Vec<String>>();
+        dir.write("Cargo.toml", &format!("[workspace]\nmembers = [{}]\n\n[workspace.package]\nversion = \"2.1.0\"\n",
+                                         members.join(", ")));
+        for i in 0..10 {
+            let name = format!("member{}", i);
+            dir.write(&format!("{}/Cargo.toml", name),
+                      &manifest(&name, "").replace("version = \"1.0.0\"", "version.workspace = true"));
+            dir.write(&format!("{}/src/main.rs", name), "fn main() {}\n");
+        }
+
+        let configs = workspace_configs(dir.path(), None);
+
+        assert_eq!(configs.len(), 10);
+        assert!(configs.iter().all(|config| config.pkgver == "2.1.0"));
+        assert_eq!(workspace_reads(&dir.path().join("Cargo.toml")), 1);
+    }
+
+
+    #[test]
+    fn members_installing_the_same_binary_conflict() {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", "[workspace]\nmembers = [\"foo\", \"foo-ng\", \"bar\"]\n");
+        dir.write("foo/Cargo.toml", &manifest("foo", ""));
+        dir.write("foo/src/main.rs", "fn main() {}\n");
+        dir.write("foo-ng/Cargo.toml", &(manifest("foo-ng", "detect_bin_conflicts = true\n")
+                                         + "\n[[bin]]\nname = \"foo\"\npath = \"src/main.rs\"\n"));
+        dir.write("foo-ng/src/main.rs", "fn main() {}\n");
+        dir.write("bar/Cargo.toml", &manifest("bar", ""));
+        dir.write("bar/src/main.rs", "fn main() {}\n");
+
+        let configs = workspace_configs(dir.path(), None);
+        let conflicts = |pkgname: &str| configs.iter().find(|config| config.pkgname == pkgname).unwrap().conflicts.clone();
+
+        assert_eq!(conflicts("foo"), ["foo-ng"]);
+        assert!(conflicts("foo-ng").is_empty());
+        assert!(conflicts("bar").is_empty());
+    }
+
+
+    #[test]
+    fn member_inherits_the_workspace_version() {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", "[workspace]\nmembers = [\"crates/foo\"]\n\n\
+                                 [workspace.package]\nversion = \"3.2.1\"\nlicense = \"Apache-2.0\"\n\
+                                 description = \"A shared foo\"\nauthors = [\"Jane Doe <jane@example.org>\"]\n");
+        dir.write("crates/foo/Cargo.toml", &manifest("foo", "arch = [\"x86_64\"]\n")
+                                               .replace("version = \"1.0.0\"", "version.workspace = true")
+                                               .replace("license = \"MIT\"", "license = { workspace = true }")
+                                               .replace("description = \"A foo\"", "description.workspace = true\n\
+                                                                                      authors.workspace = true"));
+        dir.write("crates/foo/src/main.rs", "fn main() {}\n");
+
+        let config = ArchConfig::new(dir.path().join("crates/foo").to_str(), None);
+
+        assert_eq!(config.pkgver, "3.2.1");
+        assert_eq!(config.license, ["Apache-2.0"]);
+        let pkgbuild = config.render_pkgbuild();
+        assert!(pkgbuild.contains("\npkgver=3.2.1\n"));
+        assert!(pkgbuild.contains("\npkgdesc=\"A shared foo\"\n"), "{}", pkgbuild);
+        assert!(pkgbuild.contains("\n# Maintainer: Jane Doe <jane@example.org>\n"), "{}", pkgbuild);
+    }
+
+
+    #[test]
+    fn package_selects_one_member() {
+        let dir = workspace();
+
+        let app = workspace_member_config(dir.path(), None, "app").unwrap();
+        assert_eq!(app.pkgname, "app");
+        assert_eq!(app.depends, ["libutil"]);
+        assert_eq!(app.output_dir, dir.path().join("app"));
+
+        let missing = workspace_member_config(dir.path(), None, "ap").unwrap_err();
+        assert_eq!(missing, "no workspace member named `ap`, the members are: app, util, extra");
+        let other_section = workspace_member_config(dir.path(), Some("aur"), "app").unwrap_err();
+        assert_eq!(other_section, "workspace member `app` has no [package.metadata.aur] section");
+    }
+}
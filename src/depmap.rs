@@ -0,0 +1,144 @@
+//! Crate to pacman package mapping shared across manifests with `--depmap`.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::config::{ArchConfig, FieldSource, add_package};
+
+
+/// Read a `crate = "pacman-package"` map, TOML or JSON depending on the extension
+pub fn load(path: &Path) -> Result<BTreeMap<String, String>, String> {
+    let content = fs::read_to_string(path)
+                     .map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => toml::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e)),
+        Some("json") => serde_json::from_str(&content).map_err(|e| format!("{}: {}", path.display(), e)),
+        _ => Err(format!("{} is neither a .toml nor a .json file", path.display())),
+    }
+}
+
+/// Add the packages `depmap` maps the crate dependencies `depends_from` picked to,
+/// the manifest's own `depmap` entries take precedence.
+pub fn apply(arch_config: &mut ArchConfig, depmap: &BTreeMap<String, String>) {
+    let depends = arch_config.depends.len();
+    for dependency in &arch_config.crate_dependencies {
+        if arch_config.depmap.contains_key(dependency) {
+            continue;
+        }
+        if let Some(package) = depmap.get(dependency) {
+            add_package(&mut arch_config.depends, package, &arch_config.pkgname);
+        }
+    }
+    if arch_config.depends.len() != depends {
+        arch_config.set_provenance("depends", FieldSource::CommandLine);
+    }
+}
+
+/// Build tools the common `[build-dependencies]` run
+pub const BUILD_TOOLS: &[(&str, &str)] = &[
+    ("pkg-config", "pkgconf"),
+    ("system-deps", "pkgconf"),
+    ("cmake", "cmake"),
+    ("bindgen", "clang"),
+];
+
+/// `--detect-build-deps`, add the tools the build script needs to `makedepends`:
+/// the package the `links` key maps to in the manifest's `depmap`, then `depmap`, like `ssl = "openssl"`,
+/// otherwise `pkgconf` to probe for it, and the packages the `[build-dependencies]` map to
+/// in the manifest's `depmap`, then `depmap`, then `BUILD_TOOLS`.
+pub fn apply_build_deps(arch_config: &mut ArchConfig, depmap: &BTreeMap<String, String>) {
+    let makedepends = arch_config.makedepends.len();
+    if let Some(links) = &arch_config.links {
+        let package = arch_config.depmap.get(links)
+                                        .or_else(|| depmap.get(links))
+                                        .map(String::as_str)
+                                        .unwrap_or("pkgconf");
+        add_package(&mut arch_config.makedepends, package, &arch_config.pkgname);
+    }
+
+    for dependency in &arch_config.build_dependencies {
+        let package = arch_config.depmap.get(dependency)
+                                        .or_else(|| depmap.get(dependency))
+                                        .map(String::as_str)
+                                        .or_else(|| BUILD_TOOLS.iter()
+                                                               .find(|(tool, _)| tool == dependency)
+                                                               .map(|(_, package)| *package));
+        if let Some(package) = package {
+            add_package(&mut arch_config.makedepends, package, &arch_config.pkgname);
+        }
+    }
+    if arch_config.makedepends.len() != makedepends {
+        arch_config.set_provenance("makedepends", FieldSource::CommandLine);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TempDir, manifest};
+
+    /// The makedepends `--detect-build-deps` adds to a crate linking `ssl`
+    fn ssl_makedepends(metadata: &str, depmap: &[(&str, &str)]) -> Vec<String> {
+        let manifest = manifest("foo", metadata)
+                           .replacen("[package]\n", "[package]\nlinks = \"ssl\"\nbuild = \"build.rs\"\n", 1);
+        let mut config = ArchConfig::from_manifest_str(&manifest, None);
+        let depmap = depmap.iter()
+                           .map(|(name, package)| (name.to_string(), package.to_string()))
+                           .collect();
+        apply_build_deps(&mut config, &depmap);
+        config.makedepends
+    }
+
+    #[test]
+    fn links_map_to_their_package() {
+        assert_eq!(ssl_makedepends("", &[("ssl", "openssl")]), ["cargo", "openssl"]);
+        assert_eq!(ssl_makedepends("[package.metadata.arch.depmap]\nssl = \"openssl-1.1\"\n", &[("ssl", "openssl")]),
+                   ["cargo", "openssl-1.1"]);
+    }
+
+    #[test]
+    fn unmapped_links_need_pkgconf() {
+        assert_eq!(ssl_makedepends("", &[]), ["cargo", "pkgconf"]);
+    }
+
+
+    /// The depends of a crate depending on `openssl` and `zstd`, with `depmap` in its metadata, after `apply`
+    fn depends_with(metadata: &str, depmap: &BTreeMap<String, String>) -> Vec<String> {
+        let manifest = manifest("foo", &format!("depends_from = \"all-deps\"\n{}", metadata))
+                       + "\n[dependencies]\nopenssl = \"0.10\"\nzstd = \"0.13\"\n";
+        let mut config = ArchConfig::from_manifest_str(&manifest, None);
+        apply(&mut config, depmap);
+        config.depends
+    }
+
+    #[test]
+    fn load_a_toml_depmap() {
+        let dir = TempDir::new();
+        let file = dir.write("depmap.toml", "openssl = \"openssl\"\nzstd = \"zstd\"\n");
+
+        let depmap = load(&file).unwrap();
+        assert_eq!(depends_with("", &depmap), ["openssl", "zstd"]);
+    }
+
+    #[test]
+    fn load_a_json_depmap() {
+        let dir = TempDir::new();
+        let file = dir.write("depmap.json", "{\"openssl\": \"openssl\", \"zstd\": \"zstd\"}");
+
+        let depmap = load(&file).unwrap();
+        assert_eq!(depends_with("", &depmap), ["openssl", "zstd"]);
+        assert_eq!(depends_with("[package.metadata.arch.depmap]\nopenssl = \"openssl-1.1\"\n", &depmap),
+                   ["openssl-1.1", "zstd"]);
+    }
+
+    #[test]
+    fn depmap_needs_a_known_extension() {
+        let dir = TempDir::new();
+        let file = dir.write("depmap.yml", "openssl: openssl\n");
+        assert!(load(&file).unwrap_err().contains("neither a .toml nor a .json file"));
+        assert!(load(&dir.path().join("missing.toml")).unwrap_err().starts_with("could not read"));
+    }
+}
@@ -0,0 +1,78 @@
+//! Open generated files in the user's editor.
+
+use std::env;
+use std::process::Command;
+
+
+/// Pick the editor from `$EDITOR`, then `$VISUAL`, then `vi` if it is in `$PATH`.
+///
+/// `var` looks up environment variables, `executable` tells whether a program can be run.
+pub fn editor_command<V, E>(file: &str, var: V, executable: E) -> Option<Command>
+    where V: Fn(&str) -> Option<String>,
+          E: Fn(&str) -> bool,
+{
+    let editor = var("EDITOR")
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| var("VISUAL").filter(|editor| !editor.trim().is_empty()))
+        .or_else(|| if executable("vi") { Some("vi".to_string()) } else { None })?;
+
+    // `$EDITOR` may carry arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next()?);
+    command.args(words).arg(file);
+    Some(command)
+}
+
+/// Whether `program` can be found in `$PATH`
+pub fn in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Open `file` in the user's editor and wait until it exits
+pub fn edit(file: &str) -> Result<(), String> {
+    match editor_command(file, |name| env::var(name).ok(), in_path) {
+        Some(mut command) => {
+            command.status()
+                   .map(|_| ())
+                   .map_err(|e| format!("failed to run editor: {}", e))
+        },
+        None => {
+            log::warn!("no editor found, set $EDITOR to edit {}", file);
+            Ok(())
+        },
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The program and arguments `editor_command` runs for `PKGBUILD` with the variables in `vars`
+    fn command_line(vars: &[(&str, &str)], vi: bool) -> Option<Vec<String>> {
+        let var = |name: &str| vars.iter()
+                                   .find(|(var, _)| *var == name)
+                                   .map(|(_, value)| value.to_string());
+        editor_command("PKGBUILD", var, |program| vi && program == "vi").map(|command| {
+            std::iter::once(command.get_program())
+                .chain(command.get_args())
+                .map(|word| word.to_string_lossy().into_owned())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn editor_carries_its_arguments() {
+        assert_eq!(command_line(&[("EDITOR", "code --wait"), ("VISUAL", "emacs")], true).unwrap(),
+                   ["code", "--wait", "PKGBUILD"]);
+    }
+
+    #[test]
+    fn editor_falls_back_to_visual_then_vi() {
+        assert_eq!(command_line(&[("EDITOR", " "), ("VISUAL", "emacs")], true).unwrap(), ["emacs", "PKGBUILD"]);
+        assert_eq!(command_line(&[], true).unwrap(), ["vi", "PKGBUILD"]);
+        assert_eq!(command_line(&[], false), None);
+    }
+}
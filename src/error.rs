@@ -0,0 +1,28 @@
+//! Failure classes and the exit codes scripts can branch on.
+
+
+/// Why cargo-arch gave up, each class exits with its own code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchError {
+    /// The config, the rendered PKGBUILD or a command line value is invalid, exits with 1
+    Validation,
+    /// A file couldn't be read or written or a manifest couldn't be parsed, exits with 2
+    Io,
+    /// makepkg or another external tool failed, exits with 3
+    Tool,
+}
+
+impl ArchError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ArchError::Validation => 1,
+            ArchError::Io => 2,
+            ArchError::Tool => 3,
+        }
+    }
+}
+
+/// Exit with the code of `error`, the error itself is logged by the caller
+pub fn exit(error: ArchError) -> ! {
+    std::process::exit(error.exit_code())
+}
@@ -0,0 +1,362 @@
+//! `cargo arch init`, prepare a crate for packaging.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::checksum::{self, ChecksumAlgo};
+use crate::config::{ArchConfig, Cargo, Severity, ToPackageConfig};
+
+
+/// What makepkg leaves next to the PKGBUILD.
+///
+/// `src/` is makepkg's `$srcdir` but also the crate's own sources, so it must not be ignored,
+/// and the PKGBUILD and .SRCINFO are usually committed.
+pub const GITIGNORE_PATTERNS: &[&str] = &[
+    "/pkg/",
+    "*.pkg.tar.*",
+];
+
+/// Append a `[package.metadata.<section>]` table to the Cargo.toml in `dir` unless it has one.
+///
+/// Returns whether the manifest was changed.
+pub fn add_metadata_section(dir: &Path, section: &str) -> std::io::Result<bool> {
+    let path = dir.join("Cargo.toml");
+    let mut content = fs::read_to_string(&path)?;
+    let header = format!("[package.metadata.{}]", section);
+
+    if content.lines().any(|line| line.trim() == header) {
+        return Ok(false);
+    }
+
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push('\n');
+    content.push_str(&header);
+    content.push('\n');
+    content.push_str("arch = [\"x86_64\"]\n");
+
+    fs::write(&path, content)?;
+    Ok(true)
+}
+
+/// Append the build artifacts to `dir/.gitignore`, creating it if needed
+/// and skipping patterns already listed.
+///
+/// Returns the patterns added.
+pub fn update_gitignore(dir: &Path) -> std::io::Result<Vec<&'static str>> {
+    let path = dir.join(".gitignore");
+    let mut content = if path.exists() { fs::read_to_string(&path)? } else { String::new() };
+
+    let missing = GITIGNORE_PATTERNS.iter()
+                                    .copied()
+                                    .filter(|pattern| !content.lines().any(|line| line.trim() == *pattern))
+                                    .collect::<Vec<&str>>();
+
+    if missing.is_empty() {
+        return Ok(missing);
+    }
+
+    if !content.is_empty() && !content.ends_with('\n') {
+        content.push('\n');
+    }
+    for pattern in &missing {
+        content.push_str(pattern);
+        content.push('\n');
+    }
+
+    fs::write(&path, content)?;
+    Ok(missing)
+}
+
+/// Recompute the checksum arrays of the `[package.metadata.<section>]` table in `dir/Cargo.toml`,
+/// editing their lines in place so comments and the order of the other keys survive.
+///
+/// Arrays the table doesn't have are left out, `sha256sums` is added when it has none.
+/// Remote sources aren't downloaded, they keep the sum they're pinned to.
+/// `[[package.metadata.<section>.source]]` tables get their own checksum updated instead.
+/// Returns the arrays written.
+pub fn update_checksums(dir: &Path, section: &str) -> Result<Vec<String>, String> {
+    let path = dir.join("Cargo.toml");
+    let mut arch_config = Cargo::from_dir(dir, Some(section)).to_config();
+
+    let mut algos = ChecksumAlgo::ALL.iter()
+                                     .copied()
+                                     .filter(|algo| !arch_config.checksums_mut(&algo.array()).unwrap().is_empty())
+                                     .collect::<Vec<ChecksumAlgo>>();
+    if algos.is_empty() {
+        algos.push(ChecksumAlgo::Sha256);
+    }
+
+    let pinned = algos.iter()
+                      .map(|algo| arch_config.checksums_mut(&algo.array()).unwrap().clone())
+                      .collect::<Vec<Vec<String>>>();
+
+    let mut content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    let mut updated = vec![];
+    for (algo, pinned) in algos.into_iter().zip(pinned) {
+        *arch_config.checksums_mut(&algo.array()).unwrap() = pinned;
+        checksum::compute_checksums(&mut arch_config, algo, false);
+
+        content = replace_checksums(&content, section, &algo.array(), arch_config.checksums_mut(&algo.array()).unwrap())?;
+        updated.push(algo.array());
+    }
+
+    for diagnostic in arch_config.diagnostics.iter() {
+        let level = match diagnostic.severity {
+            Severity::Warning => log::Level::Warn,
+            Severity::Error => log::Level::Error,
+        };
+        log::log!(level, "{}: {}", diagnostic.field, diagnostic.message);
+    }
+
+    fs::write(&path, content).map_err(|e| e.to_string())?;
+    Ok(updated)
+}
+
+/// `--write-back`, put the computed `array` of `arch_config` into its Cargo.toml,
+/// editing the line in place like `update_checksums`.
+pub fn write_back_checksums(arch_config: &mut ArchConfig, section: &str, array: &str) -> Result<PathBuf, String> {
+    let path = arch_config.manifest_dir.join("Cargo.toml");
+    let content = fs::read_to_string(&path).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+    let content = replace_checksums(&content, section, array, arch_config.checksums_mut(array).unwrap())?;
+    fs::write(&path, content).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Set `array` in `[package.metadata.<section>]` to `sums`, or when the sources are
+/// `[[package.metadata.<section>.source]]` tables, whose checksums take precedence,
+/// each table's own checksum like `sha256` to its sum.
+fn replace_checksums(content: &str, section: &str, array: &str, sums: &[String]) -> Result<String, String> {
+    let quote = |sum: &String| toml::Value::String(sum.clone()).to_string();
+
+    let sources = content.parse::<toml::Value>()
+                         .ok()
+                         .and_then(|manifest| manifest.get("package")?
+                                                      .get("metadata")?
+                                                      .get(section)?
+                                                      .get("source")?
+                                                      .as_array()
+                                                      .cloned())
+                         .unwrap_or_default();
+    if !sources.iter().any(toml::Value::is_table) {
+        let sums = sums.iter().map(quote).collect::<Vec<String>>();
+        return replace_key(content, &format!("[package.metadata.{}]", section), 0, array, &format!("[{}]", sums.join(", ")));
+    }
+
+    let header = format!("[[package.metadata.{}.source]]", section);
+    let tables = content.lines().filter(|line| line.trim() == header).count();
+    if tables != sources.len() || tables != sums.len() {
+        return Err(format!("only `{}` tables can be updated, not inline ones in a `source` array", header));
+    }
+
+    let key = array.trim_end_matches("sums");
+    let mut content = content.to_string();
+    for (i, sum) in sums.iter().enumerate() {
+        content = replace_key(&content, &header, i, key, &quote(sum))?;
+    }
+    Ok(content)
+}
+
+/// Replace the value of `key` in the `nth` table starting at a `header` line, which may span several lines,
+/// or append `key = value` to the table when it has no such key.
+fn replace_key(content: &str, header: &str, nth: usize, key: &str, value: &str) -> Result<String, String> {
+    let lines = content.split_inclusive('\n').collect::<Vec<&str>>();
+    let start = lines.iter()
+                     .enumerate()
+                     .filter(|(_, line)| line.trim() == header)
+                     .nth(nth)
+                     .map(|(i, _)| i)
+                     .ok_or_else(|| format!("no `{}` table", header))?
+                     + 1;
+    let end = lines[start..].iter()
+                            .position(|line| line.trim_start().starts_with('['))
+                            .map(|end| start + end)
+                            .unwrap_or(lines.len());
+
+    let is_key = |line: &str| line.trim_start()
+                                  .strip_prefix(key)
+                                  .map(|rest| rest.trim_start().starts_with('='))
+                                  .unwrap_or(false);
+
+    let mut buffer = lines[..start].concat();
+    match (start..end).find(|&i| is_key(lines[i])) {
+        Some(first) => {
+            // the value ends at the `]` closing its array, or with its line for anything else
+            let mut depth = 0;
+            let mut quote = None;
+            let mut value_end = None;
+            for (i, line) in lines.iter().enumerate().take(end).skip(first) {
+                let from = if i == first { line.find('=').unwrap() + 1 } else { 0 };
+                let mut comment = None;
+                for (j, c) in line[from..].char_indices() {
+                    match (quote, c) {
+                        (Some(q), c) if q == c => quote = None,
+                        (Some(_), _) => {},
+                        (None, '"') | (None, '\'') => quote = Some(c),
+                        (None, '#') => {
+                            comment = Some(from + j);
+                            break;
+                        },
+                        (None, '[') => depth += 1,
+                        (None, ']') => {
+                            depth -= 1;
+                            if depth == 0 {
+                                value_end = Some((i, from + j + 1));
+                                break;
+                            }
+                        },
+                        _ => {},
+                    }
+                }
+                if value_end.is_none() && depth == 0 {
+                    let line_end = line.trim_end_matches(['\n', '\r']).len();
+                    value_end = Some((i, comment.unwrap_or(line_end)));
+                }
+                if value_end.is_some() {
+                    break;
+                }
+            }
+            let (last, offset) = value_end.ok_or_else(|| format!("unclosed `{}` array", key))?;
+
+            let indent = &lines[first][..lines[first].len() - lines[first].trim_start().len()];
+            buffer.push_str(&lines[start..first].concat());
+            buffer.push_str(&format!("{}{} = {}{}", indent, key, value, &lines[last][offset..]));
+            buffer.push_str(&lines[last + 1..].concat());
+        },
+        None => {
+            let last = (start..end).rev()
+                                   .find(|&i| !lines[i].trim().is_empty())
+                                   .map(|i| i + 1)
+                                   .unwrap_or(start);
+            buffer.push_str(&lines[start..last].concat());
+            if !buffer.ends_with('\n') {
+                buffer.push('\n');
+            }
+            buffer.push_str(&format!("{} = {}\n", key, value));
+            buffer.push_str(&lines[last..].concat());
+        },
+    }
+
+    Ok(buffer)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TempDir, manifest};
+
+    const SUM_A: &str = "87428fc522803d31065e7bce3cf03fe475096631e5e07bbd7a0fde60c4cf25c7";
+    const SUM_B: &str = "0263829989b6fd954f72baaf2fc64bc2e2f01d692d4de72986ea808f6e99813f";
+
+    /// A crate with the local sources `a.txt` and `b.txt`, and `metadata` as its `[package.metadata.arch]`
+    fn crate_with_sources(metadata: &str) -> TempDir {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", metadata));
+        dir.write("a.txt", "a\n");
+        dir.write("b.txt", "b\n");
+        dir
+    }
+
+    #[test]
+    fn update_checksums_keeps_comments() {
+        let dir = crate_with_sources("source = [\"a.txt\", \"b.txt\"]\n\
+                                      # regenerated by cargo arch init --update\n\
+                                      sha256sums = [\"stale\",\n              \"stale\"]\n\
+                                      depends = [\"glibc\"]\n");
+
+        assert_eq!(update_checksums(dir.path(), "arch").unwrap(), ["sha256sums"]);
+
+        let content = fs::read_to_string(dir.path().join("Cargo.toml")).unwrap();
+        assert!(content.contains(&format!("# regenerated by cargo arch init --update\n\
+                                           sha256sums = [\"{}\", \"{}\"]\n\
+                                           depends = [\"glibc\"]\n", SUM_A, SUM_B)));
+    }
+
+    #[test]
+    fn update_checksums_keeps_pinned_remote_sums() {
+        let dir = crate_with_sources("source = [\"https://example.org/foo.tar.gz\", \"a.txt\"]\n\
+                                      sha256sums = [\"0123abcd\", \"stale\"]\n");
+
+        assert_eq!(update_checksums(dir.path(), "arch").unwrap(), ["sha256sums"]);
+        assert_eq!(Cargo::from_dir(dir.path(), None).to_config().sha256sums, ["0123abcd", SUM_A]);
+    }
+
+    #[test]
+    fn update_checksums_of_source_tables() {
+        let dir = crate_with_sources("\n[[package.metadata.arch.source]]\nurl = \"a.txt\"\n# pinned upstream\nsha256 = \"stale\"\n\
+                                      \n[[package.metadata.arch.source]]\nurl = \"b.txt\"\n");
+
+        assert_eq!(update_checksums(dir.path(), "arch").unwrap(), ["sha256sums"]);
+
+        let content = fs::read_to_string(dir.path().join("Cargo.toml")).unwrap();
+        assert!(content.contains(&format!("url = \"a.txt\"\n# pinned upstream\nsha256 = \"{}\"\n", SUM_A)));
+        assert!(content.ends_with(&format!("url = \"b.txt\"\nsha256 = \"{}\"\n", SUM_B)));
+        assert_eq!(Cargo::from_dir(dir.path(), None).to_config().sha256sums, [SUM_A, SUM_B]);
+    }
+
+    #[test]
+    fn inline_source_tables_are_not_updated() {
+        let dir = crate_with_sources("source = [{ url = \"a.txt\", sha256 = \"stale\" }]\n");
+
+        assert!(update_checksums(dir.path(), "arch").is_err());
+        assert!(fs::read_to_string(dir.path().join("Cargo.toml")).unwrap().contains("sha256 = \"stale\""));
+    }
+
+    /// `--checksums --write-back`, then the sums read back from the manifest
+    fn write_back_and_reread(dir: &TempDir) -> Vec<String> {
+        let mut arch_config = Cargo::from_dir(dir.path(), None).to_config();
+        checksum::compute_checksums(&mut arch_config, ChecksumAlgo::Sha256, false);
+        write_back_checksums(&mut arch_config, "arch", "sha256sums").unwrap();
+        Cargo::from_dir(dir.path(), None).to_config().sha256sums
+    }
+
+    #[test]
+    fn written_back_checksums_are_read_back() {
+        let dir = crate_with_sources("source = [\"a.txt\", \"b.txt\"]  # both local\n");
+
+        assert_eq!(write_back_and_reread(&dir), [SUM_A, SUM_B]);
+        let content = fs::read_to_string(dir.path().join("Cargo.toml")).unwrap();
+        assert!(content.contains("# both local\n"));
+    }
+
+    #[test]
+    fn failed_downloads_keep_the_written_back_sum() {
+        let dir = crate_with_sources("source = [\"file:///nonexistent/foo.tar.gz\", \"a.txt\"]\n\
+                                      sha256sums = [\"0123abcd\", \"stale\"]\n");
+
+        let mut arch_config = Cargo::from_dir(dir.path(), None).to_config();
+        checksum::compute_checksums(&mut arch_config, ChecksumAlgo::Sha256, true);
+        assert!(arch_config.diagnostics.has_errors());
+        assert_eq!(arch_config.sha256sums, ["0123abcd", SUM_A]);
+    }
+
+    #[test]
+    fn written_back_table_checksums_are_read_back() {
+        let dir = crate_with_sources("\n[[package.metadata.arch.source]]\nurl = \"a.txt\"\nsha256 = \"stale\"\n\
+                                      \n[[package.metadata.arch.source]]\nurl = \"b.txt\"\n");
+
+        assert_eq!(write_back_and_reread(&dir), [SUM_A, SUM_B]);
+        assert_eq!(write_back_and_reread(&dir), [SUM_A, SUM_B]);
+    }
+
+
+    #[test]
+    fn gitignore_appends_are_idempotent() {
+        let dir = TempDir::new();
+        dir.write(".gitignore", "/target\n/pkg/");
+
+        assert_eq!(update_gitignore(dir.path()).unwrap(), ["*.pkg.tar.*"]);
+        assert!(update_gitignore(dir.path()).unwrap().is_empty());
+        assert_eq!(fs::read_to_string(dir.path().join(".gitignore")).unwrap(), "/target\n/pkg/\n*.pkg.tar.*\n");
+    }
+
+    #[test]
+    fn gitignore_is_created() {
+        let dir = TempDir::new();
+
+        assert_eq!(update_gitignore(dir.path()).unwrap(), GITIGNORE_PATTERNS);
+        assert_eq!(fs::read_to_string(dir.path().join(".gitignore")).unwrap(), "/pkg/\n*.pkg.tar.*\n");
+    }
+}
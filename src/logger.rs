@@ -0,0 +1,100 @@
+//! Diagnostics output on stderr through the `log` facade.
+
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+
+/// Prints `cargo-arch: <level>: <message>` on stderr
+struct Logger;
+
+static LOGGER: Logger = Logger;
+
+/// Whether the level names on stderr are colored
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        let color = COLOR.load(Ordering::Relaxed);
+        match record.level() {
+            Level::Error => eprintln!("cargo-arch: {}: {}", paint("error", Level::Error, color), record.args()),
+            Level::Warn => eprintln!("cargo-arch: {}: {}", paint("warning", Level::Warn, color), record.args()),
+            Level::Info => eprintln!("cargo-arch: {}", record.args()),
+            Level::Debug | Level::Trace => eprintln!("cargo-arch: debug: {}", record.args()),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+/// `--quiet` shows errors only, `--verbose` adds the debug messages to the default warnings and infos
+pub fn level(quiet: bool, verbose: bool) -> LevelFilter {
+    if quiet {
+        LevelFilter::Error
+    } else if verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    }
+}
+
+/// `--color`, `auto` colors a terminal unless `NO_COLOR` is set
+pub fn use_color(choice: &str, is_terminal: bool) -> bool {
+    match choice {
+        "always" => true,
+        "never" => false,
+        _ => is_terminal && std::env::var_os("NO_COLOR").map(|value| value.is_empty()).unwrap_or(true),
+    }
+}
+
+/// Whether `--color` colors stdout
+pub fn stdout_color(choice: &str) -> bool {
+    use_color(choice, std::io::stdout().is_terminal())
+}
+
+/// `text` in red for errors and yellow for warnings
+pub fn paint(text: &str, level: Level, color: bool) -> String {
+    match (color, level) {
+        (true, Level::Error) => format!("\x1b[31m{}\x1b[0m", text),
+        (true, Level::Warn) => format!("\x1b[33m{}\x1b[0m", text),
+        _ => text.to_string(),
+    }
+}
+
+/// Install the logger
+pub fn init(level: LevelFilter, color: &str) {
+    COLOR.store(use_color(color, std::io::stderr().is_terminal()), Ordering::Relaxed);
+    log::set_logger(&LOGGER).expect("logger already set");
+    log::set_max_level(level);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+
+    use super::*;
+    use crate::testing::with_env;
+
+    #[test]
+    fn auto_colors_a_terminal_unless_no_color() {
+        with_env(&[("NO_COLOR", None)], || {
+            assert!(use_color("auto", true));
+            assert!(!use_color("auto", false));
+            assert!(!use_color("never", true));
+        });
+        with_env(&[("NO_COLOR", Some(OsStr::new("1")))], || {
+            assert!(!use_color("auto", true));
+            assert!(use_color("always", false));
+        });
+    }
+}
@@ -7,55 +7,373 @@ extern crate serde_derive;
 
 use clap::{App, load_yaml};
 
+use crate::error::ArchError;
+
+pub mod checksum;
 pub mod config;
+pub mod depmap;
+pub mod editor;
+pub mod error;
+pub mod init;
+pub mod logger;
+pub mod makepkg;
+pub mod nvchecker;
+pub mod pkgbuild;
+pub mod registry;
+pub mod repos;
+pub mod soname;
+#[cfg(test)]
+mod testing;
+
+
+/// Command line switches of `validate`
+struct ValidateOptions<'a> {
+    format: &'a str,
+    color: bool,
+    strict: bool,
+    lenient: bool,
+    namcap: bool,
+    check_repos: Option<&'a str>,
+}
+
+/// Command line switches deciding what happens around the PKGBUILD generation
+struct BuildOptions {
+    strict: bool,
+    lenient: bool,
+    validate_output: bool,
+    expect_hash: Option<String>,
+    print_path: bool,
+    stdout: bool,
+    mksrcinfo: bool,
+    build: bool,
+    install: bool,
+    syncdeps: bool,
+    force: bool,
+    overwrite: bool,
+    no_clobber: bool,
+    jobs: Option<usize>,
+    reproducible: bool,
+    source_date_epoch: Option<String>,
+    edit: bool,
+    append_to: bool,
+}
 
 
-fn build_arch_package(mksrcinfo: bool,
-                      build: bool,
-                      install: bool,
-                      syncdeps: bool,
-                      force: bool,
-                      manifest_path: Option<&str>) {
-    use std::process::Command;
-    use std::fs::File;
-    use std::io::Write;
+/// Write the PKGBUILD, with `--append-to` only its variables are updated in the existing file
+fn write_pkgbuild(arch_config: &config::ArchConfig, options: &BuildOptions) {
     use crate::config::core::GeneratePackageConfig;
 
-    config::ArchConfig::new(manifest_path).generate_package_config();
+    if !options.append_to {
+        let path = arch_config.pkgbuild_path();
+        if let Ok(content) = std::fs::read_to_string(&path) {
+            if options.no_clobber {
+                log::info!("{} exists, --no-clobber leaves it as is", path.display());
+                return;
+            }
+            if !content.starts_with(config::GENERATED_BY) && !options.overwrite {
+                log::error!("{} wasn't generated by cargo-arch, pass --overwrite to overwrite it", path.display());
+                error::exit(ArchError::Io);
+            }
+        }
+        if let Err(e) = arch_config.generate_package_config() {
+            log::error!("{}", e);
+            error::exit(ArchError::Io);
+        }
+        return;
+    }
+
+    let path = arch_config.pkgbuild_path();
+    let content = std::fs::read_to_string(&path).unwrap_or_else(|e| {
+        log::error!("--append-to: could not read {}: {}", path.display(), e);
+        error::exit(ArchError::Io);
+    });
+    let content = match pkgbuild::merge_variables(&content, &arch_config.render_pkgbuild(), config::FIELDS) {
+        Ok(content) => content,
+        Err(e) => {
+            log::error!("--append-to: {}: {}", path.display(), e);
+            error::exit(ArchError::Validation);
+        },
+    };
+    if let Err(e) = std::fs::write(&path, content) {
+        log::error!("--append-to: could not write {}: {}", path.display(), e);
+        error::exit(ArchError::Io);
+    }
+    log::info!("updated the variables in {}", path.display());
+    if let Err(e) = arch_config.generate_install_script() {
+        log::error!("{}", e);
+        error::exit(ArchError::Io);
+    }
+}
+
+
+fn build_arch_package(mut arch_config: config::ArchConfig, options: &BuildOptions) {
+    let mut diagnostics = arch_config.validate();
+    if options.lenient {
+        diagnostics.demote_errors("metadata");
+    }
+    if options.strict {
+        diagnostics.promote_warnings();
+    }
+    for diagnostic in diagnostics.iter() {
+        let level = match diagnostic.severity {
+            config::Severity::Warning => log::Level::Warn,
+            config::Severity::Error => log::Level::Error,
+        };
+        log::log!(level, "{}: {}", diagnostic.field, diagnostic.message);
+    }
+    if diagnostics.has_errors() {
+        error::exit(ArchError::Validation);
+    }
+
+    if arch_config.cdylib_provides {
+        soname::add_cdylib_provides(&mut arch_config);
+    }
+
+    if options.validate_output {
+        if let Err(e) = pkgbuild::check_syntax(&arch_config.render_pkgbuild()) {
+            log::error!("{}: rendered PKGBUILD is broken: {}", arch_config.pkgname, e);
+            error::exit(ArchError::Validation);
+        }
+    }
+
+    if let Some(expected) = &options.expect_hash {
+        match checksum::ChecksumAlgo::Sha256.hash_bytes(arch_config.render_pkgbuild().as_bytes()) {
+            Ok(hash) if hash.eq_ignore_ascii_case(expected) => {},
+            Ok(hash) => {
+                log::error!("{}: rendered PKGBUILD has sha256 {}, expected {}", arch_config.pkgname, hash, expected);
+                error::exit(ArchError::Validation);
+            },
+            Err(e) => {
+                log::error!("--expect-hash: {}", e);
+                error::exit(ArchError::Tool);
+            },
+        }
+    }
+
+    if options.stdout {
+        print!("{}", arch_config.render_pkgbuild());
+        return;
+    }
+
+    write_pkgbuild(&arch_config, options);
 
-    if mksrcinfo {
-        let output = Command::new("makepkg")
-                             .args(&["--printsrcinfo"])
-                             .output()
-                             .expect("failed to generate .SRCINFO");
+    if options.edit {
+        if let Err(e) = editor::edit(&arch_config.pkgbuild_path().to_string_lossy()) {
+            log::error!("--edit: {}", e);
+            error::exit(ArchError::Tool);
+        }
+    }
+
+    if options.mksrcinfo {
+        // makepkg only reads the static `pkgver=`, for VCS packages settle it with `pkgver()` first,
+        // like makepkg does before building, so .SRCINFO doesn't claim the manifest version.
+        if arch_config.vcs {
+            match makepkg::run_pkgver(&arch_config.pkgbuild_path()) {
+                Some(pkgver) => {
+                    arch_config.pkgver = pkgver;
+                    write_pkgbuild(&arch_config, options);
+                },
+                None => log::warn!("pkgver: pkgver() failed, .SRCINFO uses {}", arch_config.pkgver),
+            }
+        }
+
+        let srcinfo = match makepkg::print_srcinfo(&arch_config.pkgbuild_path()) {
+            Ok(srcinfo) => srcinfo,
+            Err(e) => {
+                log::error!("--mksrcinfo: {}", e);
+                error::exit(ArchError::Tool);
+            },
+        };
+        let srcinfo = config::final_newline(&String::from_utf8_lossy(&srcinfo),
+                                            arch_config.final_newline.then_some("\n"));
+        if let Err(e) = std::fs::write(arch_config.srcinfo_path(), srcinfo) {
+            log::error!("could not write {}: {}", arch_config.srcinfo_path().display(), e);
+            error::exit(ArchError::Io);
+        }
+        log::info!("wrote {}", arch_config.srcinfo_path().display());
+        if let Some(debug_pkgname) = arch_config.debug_pkgname() {
+            log::info!("{} isn't listed in {}, makepkg adds it when building", debug_pkgname, arch_config.srcinfo_name);
+        }
+    }
 
-        let mut file = File::create(".SRCINFO").unwrap();
-        file.write_all(&output.stdout).unwrap();
+    if options.print_path {
+        let mut paths = vec![arch_config.pkgbuild_path()];
+        paths.extend(arch_config.install_script_path());
+        if options.mksrcinfo {
+            paths.push(arch_config.srcinfo_path());
+        }
+        for path in paths {
+            println!("{}", path.canonicalize().unwrap_or(path).display());
+        }
     }
 
     ////////////////////
     // Build Package
     ////////////////////
 
-    if build {
+    if options.build {
         let mut args = vec![];
 
-        if install {
+        if options.install {
             args.push("--install");
         }
-        if syncdeps {
+        if options.syncdeps {
             args.push("--syncdeps");
         }
-        if force {
+        if options.force {
             args.push("--force");
         }
 
-        Command::new("makepkg")
-                .args(&args)
-                .spawn()
-                .unwrap()
-                .wait()
-                .expect("failed to build package");
+        let mut env = vec![];
+        if let Some(jobs) = options.jobs {
+            env.push(("MAKEFLAGS", format!("-j{}", jobs)));
+            env.push(("CARGO_BUILD_JOBS", jobs.to_string()));
+        }
+        if options.reproducible {
+            // a stale $srcdir would leak into the package
+            args.push("--cleanbuild");
+            let epoch = options.source_date_epoch.clone()
+                                                 .or_else(|| makepkg::last_commit_time(&arch_config.manifest_dir));
+            match epoch {
+                Some(epoch) => env.push(("SOURCE_DATE_EPOCH", epoch)),
+                None => {
+                    log::error!("--reproducible: no git commit to take SOURCE_DATE_EPOCH from, pass one");
+                    error::exit(ArchError::Validation);
+                },
+            }
+        }
+
+        if let Err(e) = makepkg::build(&arch_config.pkgbuild_path(), &args, &env) {
+            log::error!("{}: {}", arch_config.pkgname, e);
+            error::exit(ArchError::Tool);
+        }
+    }
+}
+
+
+fn validate_arch_package(options: &ValidateOptions, manifest_path: Option<&str>, metadata_section: Option<&str>) {
+    let arch_config = config::ToPackageConfig::to_config(&load_cargo(manifest_path, metadata_section));
+    let mut diagnostics = arch_config.validate();
+    if options.lenient {
+        diagnostics.demote_errors("metadata");
+    }
+    if options.namcap {
+        arch_config.check_namcap(&mut diagnostics);
+    }
+    if let Some(dbpath) = options.check_repos {
+        repos::check_official_conflict(&arch_config.pkgname, std::path::Path::new(dbpath), &mut diagnostics);
+    }
+    if options.strict {
+        diagnostics.promote_warnings();
+    }
+
+    match options.format {
+        "json" => println!("{}", serde_json::to_string_pretty(&diagnostics).unwrap()),
+        "sarif" => {
+            let sarif = diagnostics.to_sarif(&arch_config.manifest_dir.join("Cargo.toml"));
+            println!("{}", serde_json::to_string_pretty(&sarif).unwrap());
+        },
+        _ => {
+            for diagnostic in diagnostics.iter() {
+                let level = match diagnostic.severity {
+                    config::Severity::Warning => log::Level::Warn,
+                    config::Severity::Error => log::Level::Error,
+                };
+                println!("cargo-arch: {}: {}: {}",
+                         logger::paint(&diagnostic.severity.to_string(), level, options.color),
+                         diagnostic.field,
+                         diagnostic.message);
+            }
+        },
+    }
+
+    if diagnostics.has_errors() {
+        error::exit(ArchError::Validation);
+    }
+}
+
+
+fn list_fields() {
+    debug_assert!(config::metadata_keys().iter().eq(config::METADATA_FIELDS.iter().map(|(name, _, _)| name)),
+                  "METADATA_FIELDS is out of sync with CargoArch");
+
+    let name_width = config::METADATA_FIELDS.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+    let type_width = config::METADATA_FIELDS.iter().map(|(_, kind, _)| kind.len()).max().unwrap_or(0);
+    for (name, kind, description) in config::METADATA_FIELDS {
+        println!("{:name_width$}  {:type_width$}  {}", name, kind, description,
+                 name_width = name_width, type_width = type_width);
+    }
+    for (alias, field) in config::ALIASES {
+        println!("{:name_width$}  alias of {}", alias, field, name_width = name_width);
+    }
+}
+
+
+/// The Cargo.toml next to `manifest_path`, an unreadable or undecodable one exits as `ArchError::Io`
+fn load_cargo(manifest_path: Option<&str>, metadata_section: Option<&str>) -> config::Cargo {
+    config::Cargo::load(&config::manifest_dir(manifest_path), metadata_section).unwrap_or_else(|e| {
+        log::error!("{}", e);
+        error::exit(ArchError::Io);
+    })
+}
+
+
+fn bump_pkgrel(file: &str) {
+    use std::fs;
+
+    let content = fs::read_to_string(file).unwrap_or_else(|e| {
+        log::error!("could not read {}: {}", file, e);
+        error::exit(ArchError::Io);
+    });
+
+    match pkgbuild::bump_pkgrel(&content) {
+        Ok(content) => {
+            if let Err(e) = fs::write(file, content) {
+                log::error!("could not write {}: {}", file, e);
+                error::exit(ArchError::Io);
+            }
+            log::info!("bumped pkgrel in {}", file);
+        },
+        Err(e) => {
+            log::error!("pkgrel: {} in {}", e, file);
+            error::exit(ArchError::Io);
+        },
+    }
+}
+
+
+fn init_arch_package(gitignore: bool, update: bool, manifest_path: Option<&str>, metadata_section: Option<&str>) {
+    let dir = config::manifest_dir(manifest_path);
+    let section = metadata_section.unwrap_or("arch");
+
+    match init::add_metadata_section(&dir, section) {
+        Ok(true) => log::info!("added [package.metadata.{}] to {}", section, dir.join("Cargo.toml").display()),
+        Ok(false) => log::info!("{} already has [package.metadata.{}]", dir.join("Cargo.toml").display(), section),
+        Err(e) => {
+            log::error!("could not update Cargo.toml: {}", e);
+            error::exit(ArchError::Io);
+        },
+    }
+
+    if update {
+        match init::update_checksums(&dir, section) {
+            Ok(updated) => log::info!("updated {} in {}", updated.join(" "), dir.join("Cargo.toml").display()),
+            Err(e) => {
+                log::error!("--update: {}", e);
+                error::exit(ArchError::Io);
+            }
+        }
+    }
+
+    if gitignore {
+        match init::update_gitignore(&dir) {
+            Ok(added) if added.is_empty() => log::info!(".gitignore is up to date"),
+            Ok(added) => log::info!("added {} to .gitignore", added.join(" ")),
+            Err(e) => {
+                log::error!("could not update .gitignore: {}", e);
+                error::exit(ArchError::Io);
+            },
+        }
     }
 }
 
@@ -69,17 +387,340 @@ fn main() {
     let yml = load_yaml!("arguments.yml");
     let arguments = App::from_yaml(yml).get_matches();
     let arguments = arguments.subcommand_matches("arch").unwrap();
+    let color = arguments.value_of("color").unwrap();
+    logger::init(logger::level(arguments.is_present("quiet"), arguments.is_present("verbose")), color);
     let build = arguments.value_of("build").unwrap().parse::<bool>().unwrap();
     let install = arguments.is_present("install");
     let syncdeps = arguments.is_present("syncdeps");
     let force = arguments.is_present("force");
+    let overwrite = arguments.is_present("overwrite");
+    let no_clobber = arguments.is_present("no-clobber");
+    let jobs = arguments.value_of("jobs").map(|jobs| jobs.parse::<usize>());
+    let reproducible = arguments.is_present("reproducible");
+    let source_date_epoch = arguments.value_of("reproducible").map(|epoch| epoch.to_string());
     let mksrcinfo = arguments.is_present("mksrcinfo");
+    let edit = arguments.is_present("edit");
+    let template_name = arguments.value_of("template-name");
+    let workspace = arguments.is_present("workspace");
+    let package = arguments.value_of("package");
+    let no_template = arguments.is_present("no-template");
+    let no_final_newline = arguments.is_present("no-final-newline");
+    let checksums = arguments.is_present("checksums");
+    let download = arguments.is_present("download");
+    let write_back = arguments.is_present("write-back");
+    let locked = arguments.is_present("locked");
+    let pkgbuild_name = arguments.value_of("pkgbuild-name").unwrap();
+    let srcinfo_name = arguments.value_of("srcinfo-name");
+    let validate_output = arguments.is_present("validate-output");
+    let expect_hash = arguments.value_of("expect-hash").map(|hash| hash.to_string());
+    let print_path = arguments.is_present("print-path");
+    let stdin = arguments.is_present("stdin");
+    let stdout = stdin || arguments.is_present("stdout");
+    let target = arguments.value_of("target");
+    let pkgrel = arguments.value_of("pkgrel");
+    let target_dir = arguments.value_of("target-dir");
+    let from_crates_io = arguments.value_of("from-crates-io");
+    let manifest_from_git = arguments.value_of("manifest-from-git");
+    let append_to = arguments.value_of("append-to").map(std::path::Path::new);
+    let offline = arguments.is_present("offline");
+    let only = arguments.value_of("only").map(config::parse_fields);
+    let exclude = arguments.value_of("exclude").map(config::parse_fields);
+    let checksum_algo = checksum::ChecksumAlgo::from_name(arguments.value_of("checksum-algo").unwrap())
+                                                .unwrap();
     let manifest_path = arguments.value_of("manifest-path");
+    let strict = arguments.is_present("strict");
+    let lenient = arguments.is_present("lenient");
+    let metadata_section = arguments.value_of("metadata-section");
+    let depmap = arguments.value_of("depmap");
+    let detect_build_deps = arguments.is_present("detect-build-deps");
+
+    ////////////////////
+    // Validate Config
+    ////////////////////
+
+    if let Some(arguments) = arguments.subcommand_matches("validate") {
+        let format = arguments.value_of("format").unwrap();
+        let manifest_path = arguments.value_of("manifest-path").or(manifest_path);
+        let strict = strict || arguments.is_present("strict");
+        let lenient = lenient || arguments.is_present("lenient");
+        let metadata_section = arguments.value_of("metadata-section").or(metadata_section);
+        let color = match arguments.occurrences_of("color") {
+            0 => color,
+            _ => arguments.value_of("color").unwrap(),
+        };
+        let check_repos = match arguments.is_present("check-repos") {
+            true => arguments.value_of("dbpath"),
+            false => None,
+        };
+        let options = ValidateOptions {
+            format,
+            color: logger::stdout_color(color),
+            strict,
+            lenient,
+            namcap: arguments.is_present("namcap-clean"),
+            check_repos,
+        };
+        validate_arch_package(&options, manifest_path, metadata_section);
+        return;
+    }
+
+    ////////////////////
+    // Init
+    ////////////////////
+
+    if let Some(arguments) = arguments.subcommand_matches("init") {
+        let manifest_path = arguments.value_of("manifest-path").or(manifest_path);
+        let metadata_section = arguments.value_of("metadata-section").or(metadata_section);
+        init_arch_package(arguments.is_present("gitignore"), arguments.is_present("update"), manifest_path, metadata_section);
+        return;
+    }
+
+    ////////////////////
+    // nvchecker
+    ////////////////////
+
+    if let Some(arguments) = arguments.subcommand_matches("nvchecker") {
+        let manifest_path = arguments.value_of("manifest-path").or(manifest_path);
+        let metadata_section = arguments.value_of("metadata-section").or(metadata_section);
+        let cargo = load_cargo(manifest_path, metadata_section);
+        let arch_config: config::ArchConfig = config::ToPackageConfig::to_config(&cargo);
+        match nvchecker::crate_repo(&cargo) {
+            Some(repo) => print!("{}", nvchecker::stanza(&arch_config.pkgname, &repo)),
+            None => log::warn!("{}: repository and homepage aren't on GitHub or GitLab, skipping",
+                               arch_config.pkgname),
+        }
+        return;
+    }
+
+    ////////////////////
+    // List Fields
+    ////////////////////
+
+    if arguments.subcommand_matches("list-fields").is_some() {
+        list_fields();
+        return;
+    }
+
+    ////////////////////
+    // Bump pkgrel
+    ////////////////////
+
+    if let Some(arguments) = arguments.subcommand_matches("bump-rel") {
+        bump_pkgrel(arguments.value_of("file").unwrap());
+        return;
+    }
+
+    ////////////////////
+    // Dump Template
+    ////////////////////
+
+    if let Some(arguments) = arguments.subcommand_matches("dump-template") {
+        let name = arguments.value_of("name").unwrap();
+        match config::template(name) {
+            Some(template) => print!("{}", template),
+            None => {
+                let names = config::TEMPLATES.iter().map(|(name, _)| *name).collect::<Vec<&str>>();
+                log::error!("--name: unknown template `{}`, expected one of {}", name, names.join(", "));
+                error::exit(ArchError::Validation);
+            }
+        }
+        return;
+    }
+
+    ////////////////////
+    // Update Checksums
+    ////////////////////
+
+    if let Some(arguments) = arguments.subcommand_matches("updsums") {
+        let file = arguments.value_of("file").unwrap();
+        if let Err(e) = checksum::update_pkgbuild_checksums(std::path::Path::new(file)) {
+            log::error!("updsums: {}", e);
+            error::exit(ArchError::Tool);
+        }
+        return;
+    }
 
     ////////////////////
     // Build Arch Package
     ////////////////////
 
-    build_arch_package(mksrcinfo, build, install, syncdeps, force, manifest_path);
+    let only = match only {
+        Some(Ok(only)) => only,
+        Some(Err(e)) => {
+            log::error!("--only: {}", e);
+            error::exit(ArchError::Validation);
+        }
+        None => vec![],
+    };
+    let exclude = match exclude {
+        Some(Ok(exclude)) => exclude.into_iter()
+                                    .filter(|field| {
+                                        let mandatory = config::MANDATORY_FIELDS.contains(&field.as_str());
+                                        if mandatory {
+                                            log::warn!("--exclude: `{}` is mandatory and is still written", field);
+                                        }
+                                        !mandatory
+                                    })
+                                    .collect(),
+        Some(Err(e)) => {
+            log::error!("--exclude: {}", e);
+            error::exit(ArchError::Validation);
+        }
+        None => vec![],
+    };
+
+    let depmap = match depmap.map(|file| depmap::load(std::path::Path::new(file))) {
+        Some(Ok(depmap)) => depmap,
+        Some(Err(e)) => {
+            log::error!("--depmap: {}", e);
+            error::exit(ArchError::Io);
+        }
+        None => Default::default(),
+    };
+
+    let jobs = match jobs {
+        Some(Ok(jobs)) if jobs > 0 => Some(jobs),
+        Some(_) => {
+            log::error!("--jobs: expected a positive number");
+            error::exit(ArchError::Validation);
+        }
+        None => None,
+    };
+
+    if let Some(epoch) = &source_date_epoch {
+        if epoch.is_empty() || !epoch.chars().all(|c| c.is_ascii_digit()) {
+            log::error!("--reproducible: `{}` is not a number of seconds since the epoch", epoch);
+            error::exit(ArchError::Validation);
+        }
+    }
+
+    if let Some(pkgrel) = pkgrel {
+        if !config::is_pkgrel(pkgrel) {
+            log::error!("--pkgrel: `{}` is not a release number like `2` or `2.1`", pkgrel);
+            error::exit(ArchError::Validation);
+        }
+    }
+
+    if let Some(target_dir) = target_dir {
+        if std::path::Path::new(target_dir).is_absolute() {
+            log::error!("--target-dir: `{}` is a path on this machine, give it relative to the crate", target_dir);
+            error::exit(ArchError::Validation);
+        }
+    }
+
+    let options = BuildOptions {
+        strict, lenient, validate_output, expect_hash, print_path, stdout, mksrcinfo,
+        build, install, syncdeps, force, overwrite, no_clobber, jobs, reproducible, source_date_epoch, edit,
+        append_to: append_to.is_some(),
+    };
+
+    if offline && (from_crates_io.is_some() || manifest_from_git.is_some() || download) {
+        log::error!("--offline: --from-crates-io, --manifest-from-git and --download need the network");
+        error::exit(ArchError::Validation);
+    }
+
+    let arch_configs = if let Some(url) = manifest_from_git {
+        match registry::from_git(url, metadata_section) {
+            Ok(arch_config) => vec![arch_config],
+            Err(e) => {
+                log::error!("--manifest-from-git: {}", e);
+                error::exit(ArchError::Tool);
+            }
+        }
+    } else if let Some(spec) = from_crates_io {
+        match registry::from_crates_io(spec, metadata_section) {
+            Ok(arch_config) => vec![arch_config],
+            Err(e) => {
+                log::error!("--from-crates-io: {}", e);
+                error::exit(ArchError::Tool);
+            }
+        }
+    } else if stdin {
+        let mut manifest = String::new();
+        if let Err(e) = std::io::Read::read_to_string(&mut std::io::stdin(), &mut manifest) {
+            log::error!("--stdin: could not read the manifest: {}", e);
+            error::exit(ArchError::Io);
+        }
+        match config::Cargo::parse(&manifest, metadata_section) {
+            Ok(cargo) => vec![config::ToPackageConfig::to_config(&cargo)],
+            Err(e) => {
+                log::error!("--stdin: {}", e);
+                error::exit(ArchError::Io);
+            }
+        }
+    } else if let Some(package) = package {
+        match config::workspace_member_config(&config::manifest_dir(manifest_path), metadata_section, package) {
+            Ok(arch_config) => vec![arch_config],
+            Err(e) => {
+                log::error!("--package: {}", e);
+                error::exit(ArchError::Validation);
+            }
+        }
+    } else if workspace {
+        config::workspace_configs(&config::manifest_dir(manifest_path), metadata_section)
+    } else {
+        vec![config::ToPackageConfig::to_config(&load_cargo(manifest_path, metadata_section))]
+    };
+
+    for mut arch_config in arch_configs {
+        if let Some(template_name) = template_name {
+            arch_config.set_template_name(template_name);
+        }
+        if no_template {
+            arch_config.include_template = false;
+        }
+        if no_final_newline {
+            arch_config.final_newline = false;
+        }
+        if locked {
+            arch_config.locked = true;
+        }
+        match append_to {
+            Some(file) => {
+                arch_config.output_dir = file.parent().unwrap_or(std::path::Path::new("")).to_path_buf();
+                let name = file.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+                arch_config.set_pkgbuild_name(&name, srcinfo_name);
+            },
+            None => arch_config.set_pkgbuild_name(pkgbuild_name, srcinfo_name),
+        }
+        arch_config.only = only.clone();
+        arch_config.exclude = exclude.clone();
+        depmap::apply(&mut arch_config, &depmap);
+        if detect_build_deps {
+            depmap::apply_build_deps(&mut arch_config, &depmap);
+        }
+        if let Some(pkgrel) = pkgrel {
+            arch_config.set_pkgrel(pkgrel);
+        }
+        if let Some(target_dir) = target_dir {
+            arch_config.cargo_target_dir = Some(target_dir.to_string());
+        }
+        if let Some(target) = target {
+            if let Err(e) = arch_config.set_target(target) {
+                log::error!("--target: {}", e);
+                error::exit(ArchError::Validation);
+            }
+        }
+        if checksums {
+            checksum::compute_checksums(&mut arch_config, checksum_algo, download);
+        }
+        if write_back && arch_config.diagnostics.has_errors() {
+            log::warn!("--write-back: not writing {} back, the sources have errors", checksum_algo.array());
+        } else if write_back {
+            match init::write_back_checksums(&mut arch_config, metadata_section.unwrap_or("arch"), &checksum_algo.array()) {
+                Ok(path) => log::info!("wrote {} to {}", checksum_algo.array(), path.display()),
+                Err(e) => {
+                    log::error!("--write-back: {}", e);
+                    error::exit(ArchError::Io);
+                }
+            }
+        }
+
+        log::debug!("{}: using the `{}` template", arch_config.pkgname, arch_config.template_name);
+        for (field, source) in arch_config.provenance() {
+            log::debug!("{}: {} from {:?}", arch_config.pkgname, field, source);
+        }
+        build_arch_package(arch_config, &options);
+    }
 
 }
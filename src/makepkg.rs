@@ -0,0 +1,154 @@
+//! Running makepkg and the generated PKGBUILD's functions.
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::process::Command;
+
+
+/// `makepkg -p <PKGBUILD>` run next to the PKGBUILD
+fn makepkg(pkgbuild: &Path) -> Command {
+    let mut command = Command::new("makepkg");
+    command.arg("-p")
+           .arg(pkgbuild.file_name().unwrap_or(OsStr::new("PKGBUILD")))
+           .current_dir(pkgbuild_dir(pkgbuild));
+    command
+}
+
+fn pkgbuild_dir(pkgbuild: &Path) -> &Path {
+    match pkgbuild.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    }
+}
+
+/// `makepkg --printsrcinfo` for `pkgbuild`
+pub fn print_srcinfo(pkgbuild: &Path) -> Result<Vec<u8>, String> {
+    let output = makepkg(pkgbuild).args(["--printsrcinfo"])
+                                  .output()
+                                  .map_err(|e| format!("could not run makepkg: {}", e))?;
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(format!("makepkg --printsrcinfo failed: {}", String::from_utf8_lossy(&output.stderr).trim()))
+    }
+}
+
+/// Run the PKGBUILD's `pkgver()` the way makepkg does before building,
+/// inside `$srcdir` which is `src/` next to the PKGBUILD.
+///
+/// Returns `None` when there is no `pkgver()` or it fails.
+pub fn run_pkgver(pkgbuild: &Path) -> Option<String> {
+    let script = r#"source "./$1" || exit 1
+type pkgver >/dev/null 2>&1 || exit 1
+srcdir="$PWD/src"
+cd "$srcdir" 2>/dev/null || true
+pkgver"#;
+
+    let output = Command::new("bash")
+                         .args(["-c", script, "bash"])
+                         .arg(pkgbuild.file_name().unwrap_or(OsStr::new("PKGBUILD")))
+                         .current_dir(pkgbuild_dir(pkgbuild))
+                         .output()
+                         .ok()?;
+
+    let pkgver = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && !pkgver.is_empty() {
+        Some(pkgver)
+    } else {
+        None
+    }
+}
+
+/// The elements of the bash array `name` once `pkgbuild` is sourced, variables expanded.
+///
+/// Returns `None` when the array isn't set.
+pub fn read_array(pkgbuild: &Path, name: &str) -> Option<Vec<String>> {
+    let script = r#"source "./$1" >/dev/null 2>&1 || exit 1
+declare -p "$2" >/dev/null 2>&1 || exit 1
+declare -n array="$2"
+printf '%s\0' "${array[@]}""#;
+
+    let output = Command::new("bash")
+                         .args(["-c", script, "bash"])
+                         .arg(pkgbuild.file_name().unwrap_or(OsStr::new("PKGBUILD")))
+                         .arg(name)
+                         .current_dir(pkgbuild_dir(pkgbuild))
+                         .output()
+                         .ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    Some(String::from_utf8_lossy(&output.stdout)
+            .split_terminator('\0')
+            .map(|element| element.to_string())
+            .collect())
+}
+
+/// `makepkg` for `pkgbuild` with `env` added to its environment
+///
+/// makepkg's output goes to stderr, stdout is kept for what cargo-arch prints.
+pub fn build(pkgbuild: &Path, args: &[&str], env: &[(&str, String)]) -> Result<(), String> {
+    let status = makepkg(pkgbuild).args(args)
+                                  .envs(env.iter().map(|(name, value)| (name, value)))
+                                  .stdout(std::io::stderr())
+                                  .status()
+                                  .map_err(|e| format!("could not run makepkg: {}", e))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("makepkg failed with {}", status))
+    }
+}
+
+/// The committer time of the last commit in `dir`, as seconds since the epoch
+pub fn last_commit_time(dir: &Path) -> Option<String> {
+    let output = Command::new("git")
+                         .args(["log", "-1", "--format=%ct"])
+                         .current_dir(dir)
+                         .output()
+                         .ok()?;
+    let time = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if output.status.success() && !time.is_empty() {
+        Some(time)
+    } else {
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchConfig, GeneratePackageConfig};
+    use crate::testing::{TempDir, git, manifest};
+
+    #[test]
+    fn pkgver_of_a_vcs_package_comes_from_git() {
+        let dir = TempDir::new();
+        dir.write("Cargo.toml", &manifest("foo", "vcs = true\n"));
+        dir.write("src/main.rs", "fn main() {}\n");
+        git(dir.path(), &["init", "--quiet"]);
+        git(dir.path(), &["add", "."]);
+        git(dir.path(), &["commit", "--quiet", "-m", "Initial commit"]);
+        git(dir.path(), &["tag", "v0.9.0"]);
+        git(dir.path(), &["commit", "--quiet", "--allow-empty", "-m", "Fix"]);
+
+        let mut config = ArchConfig::new(dir.path().to_str(), None);
+        config.output_dir = dir.path().to_path_buf();
+        config.generate_package_config().unwrap();
+
+        assert!(config.render_pkgbuild().contains("\npkgver=1.0.0\n"));
+        let pkgver = run_pkgver(&config.pkgbuild_path()).unwrap();
+        assert!(pkgver.starts_with("0.9.0.r1.g"), "{}", pkgver);
+    }
+
+    #[test]
+    fn pkgver_without_a_pkgver_function() {
+        let dir = TempDir::new();
+        let pkgbuild = dir.write("PKGBUILD", "pkgname=foo\npkgver=1.0\n");
+
+        assert_eq!(run_pkgver(&pkgbuild), None);
+    }
+}
@@ -0,0 +1,85 @@
+//! `cargo arch nvchecker`, the nvchecker stanza watching the upstream releases.
+
+use crate::config::Cargo;
+
+
+/// A repository on a forge nvchecker can query
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Repo {
+    /// nvchecker's source type, `github` or `gitlab`
+    pub source: &'static str,
+    /// The host, for self-hosted GitLab instances
+    pub host: Option<String>,
+    /// `owner/name`
+    pub path: String,
+}
+
+/// The forge repository a `repository`/`homepage` URL points to, like `https://github.com/owner/name.git`
+pub fn detect_repo(url: &str) -> Option<Repo> {
+    let rest = url.split_once("://")
+                  .map(|(_, rest)| rest)
+                  .or_else(|| url.strip_prefix("git@").map(|rest| rest.trim_start()))?;
+    let (host, path) = rest.split_once(['/', ':'])?;
+    let host = host.rsplit('@').next().unwrap_or(host).to_lowercase();
+
+    let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+    let owner = segments.next()?;
+    let name = segments.next()?;
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    let path = format!("{}/{}", owner, name);
+
+    if host == "github.com" || host == "www.github.com" {
+        Some(Repo { source: "github", host: None, path })
+    } else if host == "gitlab.com" {
+        Some(Repo { source: "gitlab", host: None, path })
+    } else if host.split('.').any(|label| label == "gitlab") {
+        Some(Repo { source: "gitlab", host: Some(host), path })
+    } else {
+        None
+    }
+}
+
+/// The `[pkgname]` stanza following the latest tag of `repo`
+pub fn stanza(pkgname: &str, repo: &Repo) -> String {
+    let mut buffer = format!("[{}]\nsource = \"{}\"\n{} = \"{}\"\n", pkgname, repo.source, repo.source, repo.path);
+    if let Some(host) = &repo.host {
+        buffer.push_str(&format!("host = \"{}\"\n", host));
+    }
+    buffer.push_str("use_max_tag = true\n");
+    buffer
+}
+
+/// The forge repository of the crate, from `package.repository` then `package.homepage`
+pub fn crate_repo(cargo: &Cargo) -> Option<Repo> {
+    cargo.package.repository.iter()
+                            .chain(cargo.package.homepage.iter())
+                            .find_map(|url| detect_repo(url))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::manifest;
+
+    #[test]
+    fn stanza_for_a_github_repository() {
+        let manifest = manifest("foo", "pkgname = \"foo-cli\"\n")
+                           .replacen("[package]\n", "[package]\nhomepage = \"https://foo.example.org\"\n\
+                                                    repository = \"https://github.com/jane/foo.git\"\n", 1);
+        let cargo = Cargo::from_manifest_str(&manifest, None);
+        let repo = crate_repo(&cargo).unwrap();
+
+        assert_eq!(stanza("foo-cli", &repo), "[foo-cli]\nsource = \"github\"\ngithub = \"jane/foo\"\nuse_max_tag = true\n");
+    }
+
+    #[test]
+    fn detect_forge_repositories() {
+        assert_eq!(detect_repo("git@github.com:jane/foo.git").unwrap(),
+                   Repo { source: "github", host: None, path: "jane/foo".to_string() });
+        assert_eq!(detect_repo("https://gitlab.example.org/jane/foo/").unwrap(),
+                   Repo { source: "gitlab", host: Some("gitlab.example.org".to_string()), path: "jane/foo".to_string() });
+        assert_eq!(detect_repo("https://codeberg.org/jane/foo"), None);
+        assert_eq!(detect_repo("https://github.com/jane"), None);
+    }
+}
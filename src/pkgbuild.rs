@@ -0,0 +1,296 @@
+//! Edit an existing PKGBUILD in place, leaving everything else untouched.
+
+
+/// Increment the `pkgrel=` assignment, keeping its quoting and the rest of the file as is.
+///
+/// A minor release like `1.1` goes up to the next whole release, `2`.
+pub fn bump_pkgrel(content: &str) -> Result<String, String> {
+    let mut found = false;
+    let mut buffer = String::with_capacity(content.len());
+
+    for line in content.split_inclusive('\n') {
+        let value = match line.strip_prefix("pkgrel=") {
+            Some(value) if !found => value,
+            _ => {
+                buffer.push_str(line);
+                continue;
+            },
+        };
+        found = true;
+
+        let end = value.trim_end_matches(['\n', '\r']);
+        let newline = &value[end.len()..];
+        let quote = match end.chars().next() {
+            Some(c @ '\'') | Some(c @ '"') => Some(c),
+            _ => None,
+        };
+        let pkgrel = match quote {
+            Some(quote) => end.trim_matches(quote),
+            None => end.trim(),
+        };
+        let release = pkgrel.split('.')
+                            .next()
+                            .and_then(|release| release.parse::<u64>().ok())
+                            .ok_or_else(|| format!("`{}` is not a valid pkgrel", pkgrel))?;
+
+        buffer.push_str("pkgrel=");
+        match quote {
+            Some(quote) => buffer.push_str(&format!("{}{}{}", quote, release + 1, quote)),
+            None => buffer.push_str(&(release + 1).to_string()),
+        }
+        buffer.push_str(newline);
+    }
+
+    if found {
+        Ok(buffer)
+    } else {
+        Err("no `pkgrel=` line".to_string())
+    }
+}
+
+/// Replace the `name=(...)` array assignment, which may span several lines,
+/// with `values` single-quoted on one line.
+pub fn replace_array(content: &str, name: &str, values: &[String]) -> Result<String, String> {
+    let prefix = format!("{}=(", name);
+    let start = content.split_inclusive('\n')
+                       .scan(0, |offset, line| {
+                           let start = *offset;
+                           *offset += line.len();
+                           Some((start, line))
+                       })
+                       .find(|(_, line)| line.starts_with(&prefix))
+                       .map(|(start, _)| start)
+                       .ok_or_else(|| format!("no `{}` line", prefix))?;
+
+    let end = array_end(content, start + prefix.len())
+                  .ok_or_else(|| format!("unclosed `{}` array", prefix))?;
+
+    let values = values.iter()
+                       .map(|value| format!("'{}'", value))
+                       .collect::<Vec<String>>()
+                       .join(" ");
+
+    Ok(format!("{}{}{}){}", &content[..start], prefix, values, &content[end..]))
+}
+
+/// Where the array opened right before `from` ends, after the first `)` outside of quotes and comments
+fn array_end(content: &str, from: usize) -> Option<usize> {
+    let mut quote = None;
+    let mut comment = false;
+    let mut previous = '(';
+    for (i, c) in content[from..].char_indices() {
+        match (quote, c) {
+            _ if comment => comment = c != '\n',
+            (None, '#') if previous.is_whitespace() || previous == '(' => comment = true,
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, ')') => return Some(from + i + 1),
+            _ => {},
+        }
+        previous = c;
+    }
+    None
+}
+
+/// The variable a `name=value` line at the start of a line assigns
+fn assigned_variable(line: &str) -> Option<&str> {
+    let (name, _) = line.split_once('=')?;
+    if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Where the assignment to `name` starting at `start` ends: after the line of the closing `)`
+/// of an array, which may span several lines, otherwise after its own line
+fn assignment_end(content: &str, start: usize, name: &str) -> usize {
+    let line_end = |from: usize| content[from..].find('\n').map(|i| from + i + 1).unwrap_or(content.len());
+    let value_start = start + name.len() + 1;
+    match content[value_start..].starts_with('(') {
+        true => array_end(content, value_start + 1).map(line_end).unwrap_or_else(|| line_end(start)),
+        false => line_end(start),
+    }
+}
+
+/// Split `content` into its lines, with each assignment as a whole and the variable it assigns
+fn statements(content: &str) -> Vec<(Option<&str>, &str)> {
+    let mut statements = vec![];
+    let mut offset = 0;
+
+    while offset < content.len() {
+        let line_end = content[offset..].find('\n').map(|i| offset + i + 1).unwrap_or(content.len());
+        let name = assigned_variable(&content[offset..line_end]);
+        let end = match name {
+            Some(name) => assignment_end(content, offset, name),
+            None => line_end,
+        };
+        statements.push((name, &content[offset..end]));
+        offset = end;
+    }
+
+    statements
+}
+
+/// Put the assignments of `rendered` to the `managed` variables into `content`:
+/// each one already in `content`, arrays spanning several lines included, is replaced where it is,
+/// the others go after the last one replaced, or at the end when there was none.
+/// Every other line of `content` is kept as is.
+///
+/// Fails when the merged PKGBUILD doesn't pass `check_syntax`.
+pub fn merge_variables(content: &str, rendered: &str, managed: &[&str]) -> Result<String, String> {
+    let assignments = statements(rendered).into_iter()
+                                          .filter_map(|(name, text)| name.map(|name| (name, text)))
+                                          .filter(|(name, _)| managed.contains(name))
+                                          .collect::<Vec<(&str, &str)>>();
+
+    let mut buffer = String::with_capacity(content.len());
+    let mut merged = vec![];
+    let mut insert_at = None;
+
+    for (name, text) in statements(content) {
+        match name.and_then(|name| assignments.iter().find(|(assigned, _)| *assigned == name)) {
+            Some((name, rendered_text)) => {
+                buffer.push_str(rendered_text);
+                merged.push(*name);
+                insert_at = Some(buffer.len());
+            },
+            None => buffer.push_str(text),
+        }
+    }
+
+    let missing = assignments.iter()
+                             .filter(|(name, _)| !merged.contains(name))
+                             .map(|(_, text)| *text)
+                             .collect::<String>();
+    match insert_at {
+        Some(at) => buffer.insert_str(at, &missing),
+        None => {
+            if !buffer.is_empty() && !buffer.ends_with('\n') {
+                buffer.push('\n');
+            }
+            buffer.push_str(&missing);
+        },
+    }
+
+    check_syntax(&buffer).map_err(|e| format!("the merged PKGBUILD is broken: {}", e))?;
+    Ok(buffer)
+}
+
+/// Check that the quotes and parentheses of a PKGBUILD are balanced,
+/// reporting the line of the first one left open or closed twice.
+///
+/// This is far from a bash parser, it only tells whether makepkg can source the file at all.
+pub fn check_syntax(content: &str) -> Result<(), String> {
+    let mut quote: Option<(char, usize)> = None;
+    let mut parens: Vec<usize> = vec![];
+    let mut escaped = false;
+    let mut comment = false;
+    let mut previous = '\n';
+
+    for (i, line) in content.split_inclusive('\n').enumerate() {
+        let number = i + 1;
+
+        for c in line.chars() {
+            if comment {
+                comment = c != '\n';
+            } else if escaped {
+                escaped = false;
+            } else {
+                match (quote, c) {
+                    (Some(('\'', _)), '\'') => quote = None,
+                    (Some(('\'', _)), _) => {},
+                    (_, '\\') => escaped = true,
+                    (Some(('"', _)), '"') => quote = None,
+                    (Some(_), _) => {},
+                    (None, '\'') | (None, '"') => quote = Some((c, number)),
+                    (None, '#') if previous.is_whitespace() || previous == '(' => comment = true,
+                    (None, '(') => parens.push(number),
+                    (None, ')') => {
+                        parens.pop().ok_or_else(|| format!("line {}: unexpected `)`", number))?;
+                    },
+                    _ => {},
+                }
+            }
+            previous = c;
+        }
+    }
+
+    if let Some((quote, number)) = quote {
+        return Err(format!("line {}: unterminated `{}`", number, quote));
+    }
+    if let Some(number) = parens.pop() {
+        return Err(format!("line {}: unclosed `(`", number));
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANAGED: &[&str] = &["pkgname", "pkgver", "optdepends", "validpgpkeys", "depends"];
+
+    #[test]
+    fn merge_keeps_helper_functions() {
+        let content = "_helper() {\n  echo hi\n}\n\npkgname=foo\npkgver=1.0\n\nbuild() {\n  _helper\n}\n";
+        let rendered = "pkgname=foo\npkgver=2.0\ndepends=(\"bar\")\n";
+
+        let merged = merge_variables(content, rendered, MANAGED).unwrap();
+
+        assert_eq!(merged,
+                   "_helper() {\n  echo hi\n}\n\npkgname=foo\npkgver=2.0\ndepends=(\"bar\")\n\nbuild() {\n  _helper\n}\n");
+    }
+
+    #[test]
+    fn merge_replaces_and_inserts_multi_line_arrays() {
+        let content = "pkgname=foo\noptdepends=(\n  \"old: gone\"\n)\npkgver=1.0\n\npackage() {\n  true\n}\n";
+        let rendered = "pkgname=foo\npkgver=1.0\n\
+                        optdepends=(\n  \"bar: for (bar)\"\n  \"baz: for baz\"\n)\n\
+                        validpgpkeys=(\n  \"ABCD\"  # Jane O'Brien\n)\n";
+
+        let merged = merge_variables(content, rendered, MANAGED).unwrap();
+
+        assert_eq!(merged,
+                   "pkgname=foo\noptdepends=(\n  \"bar: for (bar)\"\n  \"baz: for baz\"\n)\npkgver=1.0\n\
+                    validpgpkeys=(\n  \"ABCD\"  # Jane O'Brien\n)\n\npackage() {\n  true\n}\n");
+        assert_eq!(merge_variables(&merged, rendered, MANAGED).unwrap(), merged);
+    }
+
+    #[test]
+    fn merge_rejects_a_broken_result() {
+        let content = "pkgname=foo\n_unclosed=(\n";
+
+        assert!(merge_variables(content, "pkgname=bar\n", MANAGED).is_err());
+    }
+
+
+    #[test]
+    fn bump_pkgrel_increments_it_in_place() {
+        assert_eq!(bump_pkgrel("pkgname=foo\npkgrel=1\n\nbuild() {\n  pkgrel=7\n}\n").unwrap(),
+                   "pkgname=foo\npkgrel=2\n\nbuild() {\n  pkgrel=7\n}\n");
+        assert_eq!(bump_pkgrel("pkgrel='9'\r\n").unwrap(), "pkgrel='10'\r\n");
+        assert_eq!(bump_pkgrel("pkgrel=\"1.1\"\n").unwrap(), "pkgrel=\"2\"\n");
+    }
+
+    #[test]
+    fn bump_pkgrel_needs_a_valid_pkgrel() {
+        assert!(bump_pkgrel("pkgname=foo\n").is_err());
+        assert!(bump_pkgrel("pkgrel=$_rel\n").is_err());
+    }
+
+
+    #[test]
+    fn check_syntax_reports_the_first_broken_line() {
+        assert_eq!(check_syntax("pkgname=foo\ndepends=(\"bar\"\n\nbuild() {\n  true\n}\n").unwrap_err(),
+                   "line 2: unclosed `(`");
+        assert_eq!(check_syntax("pkgname=foo\npkgdesc=\"A \\\"foo\n").unwrap_err(), "line 2: unterminated `\"`");
+        assert_eq!(check_syntax("pkgname=foo\narch=('x86_64'))\n").unwrap_err(), "line 2: unexpected `)`");
+    }
+
+    #[test]
+    fn check_syntax_accepts_quotes_in_comments_and_escapes() {
+        assert!(check_syntax("# Maintainer: Jane O'Brien\npkgdesc=\"A \\\"quoted\\\" (foo)\"\nurl='a#b'\n").is_ok());
+    }
+}
@@ -0,0 +1,184 @@
+//! Package a crate published on crates.io or living in a git repository instead of a local checkout.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::checksum::{self, ChecksumAlgo};
+use crate::config::{ArchConfig, Cargo, ToPackageConfig, add_package};
+
+
+/// Where crates.io serves the `.crate` tarballs from
+pub const DOWNLOAD_URL: &str = "https://static.crates.io/crates";
+
+/// Split `name@version`
+pub fn parse_spec(spec: &str) -> Result<(&str, &str), String> {
+    match spec.split_once('@') {
+        Some((name, version)) if !name.is_empty() && !version.is_empty() => Ok((name, version)),
+        _ => Err(format!("`{}` is not of the form `name@version`", spec)),
+    }
+}
+
+/// The `source` entry downloading `name`'s `version` tarball from the registry at `download_url`
+pub fn source(download_url: &str, name: &str, version: &str) -> String {
+    format!("{}-{}.tar.gz::{}/{}/{}-{}.crate", name, version, download_url, name, name, version)
+}
+
+/// Download the published crate, read its manifest and point the PKGBUILD's source at it
+pub fn from_crates_io(spec: &str, metadata_section: Option<&str>) -> Result<ArchConfig, String> {
+    from_registry(spec, DOWNLOAD_URL, metadata_section)
+}
+
+/// `from_crates_io` with the tarballs served from `download_url` instead
+fn from_registry(spec: &str, download_url: &str, metadata_section: Option<&str>) -> Result<ArchConfig, String> {
+    let (name, version) = parse_spec(spec)?;
+    let source = source(download_url, name, version);
+
+    let download_dir = std::env::temp_dir().join(format!("cargo-arch-crates-io-{}", std::process::id()));
+    fs::create_dir_all(&download_dir).map_err(|e| e.to_string())?;
+
+    let fetch = || {
+        let tarball = checksum::download(&source, &download_dir)
+            .map_err(|e| format!("could not download {}@{} from crates.io: {}", name, version, e))?;
+        let sha256sum = ChecksumAlgo::Sha256.hash_file(&tarball)?;
+
+        let output = Command::new("tar")
+                             .arg("-xzf")
+                             .arg(&tarball)
+                             .arg("-C")
+                             .arg(&download_dir)
+                             .output()
+                             .map_err(|e| format!("could not run tar: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+
+        let crate_dir = format!("{}-{}", name, version);
+        let mut arch_config = Cargo::from_dir(&download_dir.join(&crate_dir), metadata_section).to_config();
+        arch_config.source = vec![source.clone()];
+        for algo in ChecksumAlgo::ALL {
+            arch_config.checksums_mut(&algo.array()).unwrap().clear();
+        }
+        arch_config.sha256sums = vec![sha256sum];
+        arch_config.build_dir = Some(crate_dir);
+        arch_config.manifest_dir = PathBuf::from(".");
+        Ok(arch_config)
+    };
+
+    let result = fetch();
+    let _ = fs::remove_dir_all(&download_dir);
+    result
+}
+
+/// The directory makepkg checks a `git+<url>` source out into, the repository name
+pub fn git_checkout_dir(url: &str) -> &str {
+    let name = url.trim_end_matches('/').rsplit('/').next().unwrap_or(url);
+    name.strip_suffix(".git").unwrap_or(name)
+}
+
+/// Shallow clone the repository at `url`, read its manifest and make a `-git` package building its head
+pub fn from_git(url: &str, metadata_section: Option<&str>) -> Result<ArchConfig, String> {
+    let clone_dir = std::env::temp_dir().join(format!("cargo-arch-git-{}", std::process::id()));
+
+    let fetch = || {
+        let output = Command::new("git")
+                             .args(["clone", "--quiet", "--depth", "1", url])
+                             .arg(&clone_dir)
+                             .output()
+                             .map_err(|e| format!("could not run git: {}", e))?;
+        if !output.status.success() {
+            return Err(format!("could not clone {}: {}", url, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+        if !clone_dir.join("Cargo.toml").is_file() {
+            return Err(format!("{} has no Cargo.toml at its root", url));
+        }
+
+        let mut cargo = Cargo::from_dir(&clone_dir, metadata_section);
+        cargo.vcs = true;
+        let mut arch_config = cargo.to_config();
+        arch_config.source = vec![format!("git+{}", url)];
+        // makepkg needs git to check the source out
+        add_package(&mut arch_config.makedepends, "git", &arch_config.pkgname.clone());
+        for algo in ChecksumAlgo::ALL {
+            arch_config.checksums_mut(&algo.array()).unwrap().clear();
+        }
+        arch_config.sha256sums = vec!["SKIP".to_string()];
+        arch_config.build_dir = Some(git_checkout_dir(url).to_string());
+        arch_config.manifest_dir = PathBuf::from(".");
+        Ok(arch_config)
+    };
+
+    let result = fetch();
+    let _ = fs::remove_dir_all(&clone_dir);
+    result
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Response, TempDir, git, http_server, manifest};
+
+    /// The `.crate` tarball of `name` at `version`, as crates.io serves it
+    fn crate_tarball(name: &str, version: &str) -> Vec<u8> {
+        let dir = TempDir::new();
+        let crate_dir = format!("{}-{}", name, version);
+        dir.write(&format!("{}/Cargo.toml", crate_dir),
+                  &manifest(name, "arch = [\"x86_64\"]\n").replace("1.0.0", version));
+        dir.write(&format!("{}/src/main.rs", crate_dir), "fn main() {}\n");
+        let output = Command::new("tar")
+                             .arg("-czf")
+                             .arg(dir.path().join("crate.tar.gz"))
+                             .arg("-C")
+                             .arg(dir.path())
+                             .arg(&crate_dir)
+                             .output()
+                             .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        fs::read(dir.path().join("crate.tar.gz")).unwrap()
+    }
+
+    #[test]
+    fn package_a_published_crate() {
+        let tarball = crate_tarball("foo", "1.2.0");
+        let url = http_server(vec![("/foo/foo-1.2.0.crate", Response::Ok(tarball.clone()))]);
+        let dir = TempDir::new();
+        let tarball_path = dir.path().join("foo.crate");
+        fs::write(&tarball_path, &tarball).unwrap();
+
+        let arch_config = from_registry("foo@1.2.0", &url, None).unwrap();
+
+        assert_eq!(arch_config.pkgname, "foo");
+        assert_eq!(arch_config.pkgver, "1.2.0");
+        assert_eq!(arch_config.source, [format!("foo-1.2.0.tar.gz::{}/foo/foo-1.2.0.crate", url)]);
+        assert_eq!(arch_config.sha256sums, [ChecksumAlgo::Sha256.hash_file(&tarball_path).unwrap()]);
+        assert_eq!(arch_config.build_dir.as_deref(), Some("foo-1.2.0"));
+
+        let missing = from_registry("foo@9.9.9", &url, None).unwrap_err();
+        assert!(missing.starts_with("could not download foo@9.9.9 from crates.io"), "{}", missing);
+    }
+
+    #[test]
+    fn package_a_git_repository() {
+        let dir = TempDir::new();
+        dir.write("work/Cargo.toml", &manifest("foo", "arch = [\"x86_64\"]\n"));
+        dir.write("work/src/main.rs", "fn main() {}\n");
+        git(&dir.path().join("work"), &["init", "--quiet"]);
+        git(&dir.path().join("work"), &["add", "."]);
+        git(&dir.path().join("work"), &["commit", "--quiet", "-m", "Initial commit"]);
+        git(dir.path(), &["clone", "--quiet", "--bare", "work", "foo.git"]);
+        let url = format!("file://{}", dir.path().join("foo.git").display());
+
+        let arch_config = from_git(&url, None).unwrap();
+
+        assert_eq!(arch_config.pkgname, "foo-git");
+        assert_eq!(arch_config.source, [format!("git+{}", url)]);
+        assert_eq!(arch_config.sha256sums, ["SKIP"]);
+        assert_eq!(arch_config.build_dir.as_deref(), Some("foo"));
+        assert!(arch_config.makedepends.contains(&"git".to_string()));
+        assert!(!std::env::temp_dir().join(format!("cargo-arch-git-{}", std::process::id())).exists());
+
+        let missing = from_git(&format!("file://{}", dir.path().join("missing.git").display()), None).unwrap_err();
+        assert!(missing.starts_with("could not clone"), "{}", missing);
+    }
+}
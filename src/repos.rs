@@ -0,0 +1,105 @@
+//! The official repositories' packages, read offline from pacman's sync databases.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+use std::process::Command;
+
+use crate::config::Diagnostics;
+
+
+/// The official repositories, AUR packages must not take their names
+pub const OFFICIAL_REPOS: &[&str] = &["core", "extra", "multilib", "core-testing", "extra-testing", "multilib-testing"];
+
+/// The package names in the `<repo>.db` files under `<dbpath>/sync`,
+/// `None` when none of them can be read.
+pub fn official_packages(dbpath: &Path) -> Option<BTreeSet<String>> {
+    let mut packages = BTreeSet::new();
+    let mut found = false;
+
+    for repo in OFFICIAL_REPOS {
+        let db = dbpath.join("sync").join(format!("{}.db", repo));
+        if !db.is_file() {
+            continue;
+        }
+        // tar detects the compression, the entries are `<pkgname>-<pkgver>-<pkgrel>/desc`
+        let output = match Command::new("tar").arg("-tf").arg(&db).output() {
+            Ok(output) if output.status.success() => output,
+            _ => continue,
+        };
+        found = true;
+        packages.extend(String::from_utf8_lossy(&output.stdout)
+                                   .lines()
+                                   .filter_map(|entry| entry.trim_start_matches("./").split('/').next())
+                                   .filter_map(|dir| dir.rsplitn(3, '-').nth(2))
+                                   .map(|pkgname| pkgname.to_string()));
+    }
+
+    if found {
+        Some(packages)
+    } else {
+        None
+    }
+}
+
+/// Warn when `pkgname` is already an official package, skipped when the sync databases can't be read
+pub fn check_official_conflict(pkgname: &str, dbpath: &Path, diagnostics: &mut Diagnostics) {
+    match official_packages(dbpath) {
+        Some(packages) if packages.contains(pkgname) => {
+            diagnostics.warning(
+                "pkgname", "official-package",
+                format!("`{}` is an official package, the AUR doesn't take duplicates", pkgname));
+        },
+        Some(_) => {},
+        None => log::info!("no sync database in {}, skipping the official package check",
+                           dbpath.join("sync").display()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TempDir;
+
+    /// A pacman database directory with an `extra` sync database holding `packages`
+    fn dbpath(packages: &[&str]) -> TempDir {
+        let dir = TempDir::new();
+        for package in packages {
+            dir.write(&format!("extra/{}/desc", package), &format!("%NAME%\n{}\n", package));
+        }
+        std::fs::create_dir_all(dir.path().join("sync")).unwrap();
+        let status = Command::new("tar").arg("-czf")
+                                        .arg(dir.path().join("sync/extra.db"))
+                                        .arg("-C")
+                                        .arg(dir.path().join("extra"))
+                                        .args(packages)
+                                        .status()
+                                        .unwrap();
+        assert!(status.success());
+        dir
+    }
+
+    fn conflicts(pkgname: &str, dbpath: &Path) -> Vec<String> {
+        let mut diagnostics = Diagnostics::new();
+        check_official_conflict(pkgname, dbpath, &mut diagnostics);
+        diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+    }
+
+    #[test]
+    fn official_package_names_conflict() {
+        let dir = dbpath(&["ripgrep-14.1.0-1", "rust-analyzer-1:2024.04.01-1"]);
+
+        assert_eq!(official_packages(dir.path()).unwrap().into_iter().collect::<Vec<String>>(),
+                   ["ripgrep", "rust-analyzer"]);
+        assert_eq!(conflicts("ripgrep", dir.path()), ["`ripgrep` is an official package, the AUR doesn't take duplicates"]);
+        assert!(conflicts("rust", dir.path()).is_empty());
+    }
+
+    #[test]
+    fn no_sync_database_skips_the_check() {
+        let dir = TempDir::new();
+
+        assert_eq!(official_packages(dir.path()), None);
+        assert!(conflicts("ripgrep", dir.path()).is_empty());
+    }
+}
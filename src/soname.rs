@@ -0,0 +1,148 @@
+//! Soname `provides` of the shared library a cdylib crate builds.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::{ArchConfig, relation_name};
+
+
+/// `readelf` output for `file`
+fn readelf(flag: &str, file: &Path) -> Option<String> {
+    let output = Command::new("readelf")
+                         .arg(flag)
+                         .arg(file)
+                         .env("LC_ALL", "C")
+                         .output()
+                         .ok()?;
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        None
+    }
+}
+
+/// The `DT_SONAME` and the ELF class (`32` or `64`) of a shared library
+pub fn read_soname(file: &Path) -> Option<(String, String)> {
+    let dynamic = readelf("-d", file)?;
+    let soname = dynamic.lines()
+                        .find_map(|line| line.split_once("Library soname: [")
+                                             .and_then(|(_, soname)| soname.split_once(']'))
+                                             .map(|(soname, _)| soname.to_string()))?;
+
+    let header = readelf("-h", file)?;
+    let class = header.lines()
+                      .find(|line| line.trim_start().starts_with("Class:"))
+                      .and_then(|line| line.trim().strip_prefix("Class:"))
+                      .map(|class| class.trim().trim_start_matches("ELF").to_string())?;
+
+    Some((soname, class))
+}
+
+/// The `provides` entry makepkg would derive from a soname, `libfoo.so.1` in ELF64 is `libfoo.so=1-64`.
+///
+/// Like makepkg, unversioned sonames provide nothing.
+pub fn soname_provides(soname: &str, class: &str) -> Option<String> {
+    let (name, version) = soname.split_once(".so.")?;
+    let major = version.split('.').next().filter(|major| !major.is_empty())?;
+    Some(format!("{}.so={}-{}", name, major, class))
+}
+
+/// The built `lib<name>.so` in the crate's target directory, the workspace's for a member
+fn find_library(arch_config: &ArchConfig, lib_name: &str) -> Option<PathBuf> {
+    if arch_config.manifest_dir.as_os_str().is_empty() {
+        return None;
+    }
+    let library = arch_config.manifest_dir
+                             .join(arch_config.host_target_dir())
+                             .join(format!("lib{}.so", lib_name));
+    Some(library).filter(|library| library.is_file())
+}
+
+/// Add the soname of the library left by a previous build to `provides`,
+/// nothing happens until it has been built.
+pub fn add_cdylib_provides(arch_config: &mut ArchConfig) {
+    add_soname_provides(arch_config, read_soname)
+}
+
+/// `add_cdylib_provides` with `read` reading the soname and the ELF class of the library
+fn add_soname_provides<R>(arch_config: &mut ArchConfig, read: R)
+    where R: Fn(&Path) -> Option<(String, String)>,
+{
+    let library = match arch_config.lib_name.clone().and_then(|lib_name| find_library(arch_config, &lib_name)) {
+        Some(library) => library,
+        None => {
+            log::debug!("{}: no shared library built yet, no soname provides", arch_config.pkgname);
+            return;
+        },
+    };
+
+    match read(&library).and_then(|(soname, class)| soname_provides(&soname, &class)) {
+        Some(provides) => {
+            let name = relation_name(&provides).to_string();
+            if !arch_config.provides.iter().any(|entry| relation_name(entry) == name) {
+                arch_config.provides.push(provides);
+            }
+        },
+        None => log::debug!("{}: {} has no versioned soname", arch_config.pkgname, library.display()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{TempDir, manifest};
+
+    /// A `foo-bar` cdylib with `provides`, built into `target/release` when `built`
+    fn cdylib(dir: &TempDir, provides: &str, built: bool) -> ArchConfig {
+        dir.write("Cargo.toml", &(manifest("foo-bar", &format!("cdylib_provides = true\nprovides = [{}]\n", provides))
+                                  + "\n[lib]\ncrate-type = [\"cdylib\"]\n"));
+        dir.write("src/lib.rs", "");
+        if built {
+            dir.write("target/release/libfoo_bar.so", "");
+        }
+        let mut arch_config = ArchConfig::new(dir.path().to_str(), None);
+        arch_config.cargo_target_dir = Some("target".to_string());
+        arch_config
+    }
+
+    fn stub(soname: &str) -> impl Fn(&Path) -> Option<(String, String)> + '_ {
+        move |library| {
+            assert!(library.ends_with("target/release/libfoo_bar.so"));
+            Some((soname.to_string(), "64".to_string()))
+        }
+    }
+
+    #[test]
+    fn soname_provides_keep_the_major_version() {
+        assert_eq!(soname_provides("libfoo.so.1", "64").unwrap(), "libfoo.so=1-64");
+        assert_eq!(soname_provides("libfoo.so.1.2.3", "32").unwrap(), "libfoo.so=1-32");
+        assert_eq!(soname_provides("libfoo.so", "64"), None);
+        assert_eq!(soname_provides("libfoo.so.", "64"), None);
+    }
+
+    #[test]
+    fn built_library_provides_its_soname() {
+        let dir = TempDir::new();
+
+        let mut arch_config = cdylib(&dir, "", true);
+        add_soname_provides(&mut arch_config, stub("libfoo_bar.so.2"));
+        assert_eq!(arch_config.provides, ["libfoo_bar.so=2-64"]);
+
+        let mut arch_config = cdylib(&dir, "\"libfoo_bar.so=1-64\"", true);
+        add_soname_provides(&mut arch_config, stub("libfoo_bar.so.2"));
+        assert_eq!(arch_config.provides, ["libfoo_bar.so=1-64"]);
+
+        let mut arch_config = cdylib(&dir, "", true);
+        add_soname_provides(&mut arch_config, stub("libfoo_bar.so"));
+        assert!(arch_config.provides.is_empty());
+    }
+
+    #[test]
+    fn unbuilt_library_provides_nothing() {
+        let dir = TempDir::new();
+        let mut arch_config = cdylib(&dir, "", false);
+        add_soname_provides(&mut arch_config, |_| panic!("nothing to read"));
+        assert!(arch_config.provides.is_empty());
+    }
+}
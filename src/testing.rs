@@ -0,0 +1,132 @@
+//! Scratch directories, environment access, git and a local HTTP server shared by the tests.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+
+static DIRS: AtomicUsize = AtomicUsize::new(0);
+static ENV: Mutex<()> = Mutex::new(());
+
+/// A fresh directory under the system temporary directory, removed with its content when dropped
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new() -> TempDir {
+        let dir = std::env::temp_dir().join(format!("cargo-arch-test-{}-{}",
+                                                    std::process::id(),
+                                                    DIRS.fetch_add(1, Ordering::SeqCst)));
+        fs::create_dir_all(&dir).unwrap();
+        TempDir(dir.canonicalize().unwrap())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    /// Write `content` into `path` relative to the directory, creating the directories on the way
+    pub fn write(&self, path: &str, content: &str) -> PathBuf {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Hold this while reading environment variables `with_env` may change, the tests run in parallel
+pub fn env_lock() -> MutexGuard<'static, ()> {
+    ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Run `f` with the environment variables in `vars` set, or removed for `None`, restoring them afterwards
+pub fn with_env<T>(vars: &[(&str, Option<&OsStr>)], f: impl FnOnce() -> T) -> T {
+    let _env = env_lock();
+    let saved = vars.iter().map(|(name, _)| (*name, std::env::var_os(name))).collect::<Vec<_>>();
+    for (name, value) in vars {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+
+    let result = f();
+
+    for (name, value) in saved {
+        match value {
+            Some(value) => std::env::set_var(name, value),
+            None => std::env::remove_var(name),
+        }
+    }
+    result
+}
+
+/// A `[package]` named `name` at version 1.0.0, with `metadata` as its `[package.metadata.arch]`
+pub fn manifest(name: &str, metadata: &str) -> String {
+    format!("[package]\nname = \"{}\"\nversion = \"1.0.0\"\ndescription = \"A {}\"\nlicense = \"MIT\"\n\n\
+             [package.metadata.arch]\n{}", name, name, metadata)
+}
+
+/// Run git with `args` in `dir`, as a committer that needs no global config
+pub fn git(dir: &Path, args: &[&str]) {
+    let output = Command::new("git")
+                         .args(["-c", "user.name=Jane", "-c", "user.email=jane@example.org", "-c", "init.defaultBranch=main"])
+                         .args(args)
+                         .current_dir(dir)
+                         .output()
+                         .unwrap();
+    assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+}
+
+/// What the `http_server` answers for a path
+pub enum Response {
+    Ok(Vec<u8>),
+    /// `302 Found` to another path of the server
+    Redirect(&'static str),
+}
+
+/// Serve `GET` requests for the paths in `routes` on localhost, `404 Not Found` for any other,
+/// until the test process exits. Returns the server's base URL, like `http://127.0.0.1:12345`.
+pub fn http_server(routes: Vec<(&'static str, Response)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().filter_map(Result::ok) {
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            let mut header = String::new();
+            if reader.read_line(&mut request).is_err() {
+                continue;
+            }
+            while reader.read_line(&mut header).map(|read| read > 2).unwrap_or(false) {
+                header.clear();
+            }
+
+            let path = request.split_whitespace().nth(1).unwrap_or("");
+            let (status, location, body) = match routes.iter().find(|(route, _)| *route == path) {
+                Some((_, Response::Ok(body))) => ("200 OK", None, body.clone()),
+                Some((_, Response::Redirect(location))) => ("302 Found", Some(*location), vec![]),
+                None => ("404 Not Found", None, b"not found".to_vec()),
+            };
+            let mut response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n", status, body.len());
+            if let Some(location) = location {
+                response.push_str(&format!("Location: {}\r\n", location));
+            }
+            response.push_str("\r\n");
+            let _ = stream.write_all(response.as_bytes()).and_then(|_| stream.write_all(&body));
+        }
+    });
+
+    url
+}
@@ -0,0 +1,596 @@
+//! Run the `cargo-arch` binary on scratch crates, with `--build false` or a stub in place of makepkg.
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+static DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// A `foo` binary crate in a scratch directory, removed when dropped
+struct Crate(PathBuf);
+
+impl Crate {
+    /// With `metadata` as its `[package.metadata.arch]`
+    fn new(metadata: &str) -> Crate {
+        let dir = std::env::temp_dir().join(format!("cargo-arch-cli-{}-{}",
+                                                    std::process::id(),
+                                                    DIRS.fetch_add(1, Ordering::SeqCst)));
+        fs::create_dir_all(&dir).unwrap();
+        let krate = Crate(dir);
+        krate.write("Cargo.toml", &manifest("foo", metadata));
+        krate.write("src/main.rs", "fn main() {}\n");
+        krate
+    }
+
+    fn write(&self, path: &str, content: &str) {
+        let path = self.0.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+
+    /// Install a `makepkg` running `script` in the crate's `bin/`, returns a `$PATH` finding it first
+    fn stub_makepkg(&self, script: &str) -> String {
+        self.write("bin/makepkg", &format!("#!/bin/sh\n{}", script));
+        fs::set_permissions(self.path("bin/makepkg"), fs::Permissions::from_mode(0o755)).unwrap();
+        format!("{}:{}", self.path("bin").display(), std::env::var("PATH").unwrap())
+    }
+
+    fn path(&self, path: &str) -> PathBuf {
+        self.0.join(path)
+    }
+
+    fn read(&self, path: &str) -> String {
+        fs::read_to_string(self.0.join(path)).unwrap()
+    }
+
+    /// `cargo arch --build false <args>` in the crate's directory
+    fn run(&self, args: &[&str]) -> Output {
+        self.run_with_stdin(args, "")
+    }
+
+    fn run_with_stdin(&self, args: &[&str], stdin: &str) -> Output {
+        let mut child = self.command(args)
+                            .stdin(Stdio::piped())
+                            .stdout(Stdio::piped())
+                            .stderr(Stdio::piped())
+                            .spawn()
+                            .unwrap();
+        std::io::Write::write_all(child.stdin.as_mut().unwrap(), stdin.as_bytes()).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    /// The command `run` runs, for tests setting its environment
+    fn command(&self, args: &[&str]) -> Command {
+        self.build_command(&[&["--build", "false"], args].concat())
+    }
+
+    /// `cargo arch <args>` in the crate's directory, running makepkg unless `args` say otherwise
+    fn build_command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_cargo-arch"));
+        command.arg("arch")
+               .args(args)
+               .current_dir(&self.0)
+               .env_remove("CARGO_MANIFEST_DIR")
+               .env_remove("CARGO_ARCH_ARCH")
+               .env_remove("CARGO_ARCH_PKGREL")
+               .env_remove("CARGO_ARCH_PKGBUILD_NAME")
+               .env_remove("CARGO_ARCH_SRCINFO_NAME")
+               .env_remove("NO_COLOR");
+        command
+    }
+}
+
+impl Drop for Crate {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn manifest(name: &str, metadata: &str) -> String {
+    format!("[package]\nname = \"{}\"\nversion = \"1.0.0\"\ndescription = \"A {}\"\nlicense = \"MIT\"\n\n\
+             [package.metadata.arch]\narch = [\"x86_64\"]\nmaintainers = [\"Jane <jane@example.org>\"]\n{}",
+            name, name, metadata)
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+
+#[test]
+fn hand_written_pkgbuild_needs_overwrite() {
+    let krate = Crate::new("");
+    krate.write("PKGBUILD", "pkgname=mine\n");
+
+    let refused = krate.run(&[]);
+    assert_eq!(refused.status.code(), Some(2), "{}", stderr(&refused));
+    assert!(stderr(&refused).contains("pass --overwrite"));
+    assert_eq!(krate.read("PKGBUILD"), "pkgname=mine\n");
+
+    let forced = krate.run(&["--force"]);
+    assert_eq!(forced.status.code(), Some(2), "--force is makepkg's, it doesn't overwrite");
+    assert_eq!(krate.read("PKGBUILD"), "pkgname=mine\n");
+
+    let overwritten = krate.run(&["--overwrite"]);
+    assert!(overwritten.status.success(), "{}", stderr(&overwritten));
+    assert!(krate.read("PKGBUILD").starts_with("# Generated by cargo-arch"));
+}
+
+#[test]
+fn generated_pkgbuild_is_regenerated() {
+    let krate = Crate::new("");
+    assert!(krate.run(&[]).status.success());
+    krate.write("Cargo.toml", &manifest("foo", "pkgrel = \"2\"\n"));
+
+    assert!(krate.run(&[]).status.success());
+    assert!(krate.read("PKGBUILD").contains("\npkgrel=2\n"));
+}
+
+#[test]
+fn no_clobber_keeps_any_pkgbuild() {
+    let krate = Crate::new("");
+    krate.write("PKGBUILD", "pkgname=mine\n");
+
+    let output = krate.run(&["--no-clobber"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(krate.read("PKGBUILD"), "pkgname=mine\n");
+}
+
+#[test]
+fn print_path_lists_the_install_script() {
+    let krate = Crate::new("sysusers = \"foo.sysusers\"\n");
+    krate.write("foo.sysusers", "u foo - \"foo daemon\"\n");
+
+    let output = krate.run(&["--print-path"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let paths = stdout(&output).lines().map(PathBuf::from).collect::<Vec<PathBuf>>();
+    assert_eq!(paths.len(), 2, "{}", stdout(&output));
+    assert!(paths[0].ends_with("PKGBUILD"));
+    assert!(paths[1].ends_with("foo.install"));
+    assert!(paths.iter().all(|path| path.is_file()));
+}
+
+#[test]
+fn validate_reports_json() {
+    let krate = Crate::new("source = [\"htps://example.org/foo.tar.gz\"]\n");
+    krate.write("Cargo.toml", &krate.read("Cargo.toml").replace("\"A foo\"", "\"A foo.\""));
+
+    let output = krate.run(&["validate", "--format", "json"]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+
+    let report = serde_json::from_str::<serde_json::Value>(&stdout(&output)).unwrap();
+    assert_eq!(report, serde_json::json!([
+        {
+            "severity": "warning",
+            "field": "pkgdesc",
+            "check": "pkgdesc-period",
+            "message": "ends with a period, AUR descriptions don't",
+        },
+        {
+            "severity": "error",
+            "field": "source",
+            "check": "source-scheme",
+            "message": "`htps://example.org/foo.tar.gz` has no scheme makepkg supports, \
+                        use http, https, ftp or git+, hg+, bzr+, svn+ URLs, or a local file name",
+        },
+    ]));
+}
+
+#[test]
+fn template_name_overrides_the_detected_template() {
+    let krate = Crate::new("");
+
+    assert!(krate.run(&[]).status.success());
+    assert!(krate.read("PKGBUILD").contains("install -Dm755 -t \"$pkgdir/usr/bin\""));
+
+    let output = krate.run(&["--template-name", "lib"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.read("PKGBUILD").contains("cargo build --release --lib"));
+    assert!(!krate.read("PKGBUILD").contains("$pkgdir/usr/bin"));
+}
+
+#[test]
+fn no_template_writes_only_the_variables() {
+    let krate = Crate::new("");
+
+    let output = krate.run(&["--no-template"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+
+    let pkgbuild = krate.read("PKGBUILD");
+    assert!(pkgbuild.contains("\npkgname=foo\n"));
+    assert!(!pkgbuild.contains("build()"));
+    assert!(!pkgbuild.contains("package()"));
+    assert!(!pkgbuild.contains('{'));
+}
+
+#[test]
+fn strict_fails_on_warnings() {
+    let krate = Crate::new("");
+    krate.write("Cargo.toml", &krate.read("Cargo.toml").replace("\"A foo\"", "\"A foo.\""));
+
+    let lax = krate.run(&[]);
+    assert!(lax.status.success(), "{}", stderr(&lax));
+    assert!(stderr(&lax).contains("pkgdesc: ends with a period"));
+
+    fs::remove_file(krate.path("PKGBUILD")).unwrap();
+    let strict = krate.run(&["--strict"]);
+    assert_eq!(strict.status.code(), Some(1), "{}", stderr(&strict));
+    assert!(!krate.path("PKGBUILD").exists());
+
+    assert_eq!(krate.run(&["validate", "--strict"]).status.code(), Some(1));
+}
+
+#[test]
+fn locked_needs_the_lockfile() {
+    let krate = Crate::new("");
+
+    let missing = krate.run(&["--locked"]);
+    assert_eq!(missing.status.code(), Some(1), "{}", stderr(&missing));
+    assert!(stderr(&missing).contains("no Cargo.lock"));
+
+    krate.write("Cargo.lock", "version = 3\n");
+    let present = krate.run(&["--locked"]);
+    assert!(present.status.success(), "{}", stderr(&present));
+    assert!(krate.read("PKGBUILD").contains("cargo build --release --locked"));
+}
+
+#[test]
+fn pkgbuild_name_from_the_environment() {
+    let krate = Crate::new("");
+
+    let output = krate.command(&[]).env("CARGO_ARCH_PKGBUILD_NAME", "PKGBUILD.generated").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.read("PKGBUILD.generated").contains("\npkgname=foo\n"));
+    assert!(!krate.path("PKGBUILD").exists());
+
+    let output = krate.command(&["--pkgbuild-name", "foo.PKGBUILD"])
+                      .env("CARGO_ARCH_PKGBUILD_NAME", "PKGBUILD.generated")
+                      .output()
+                      .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.path("foo.PKGBUILD").is_file());
+}
+
+#[test]
+fn quiet_keeps_only_errors() {
+    let krate = Crate::new("");
+
+    let output = krate.run(&[]);
+    assert!(stderr(&output).contains("wrote "), "{}", stderr(&output));
+
+    let quiet = krate.run(&["--quiet"]);
+    assert!(quiet.status.success());
+    assert_eq!(stderr(&quiet), "");
+
+    krate.write("PKGBUILD", "pkgname=mine\n");
+    let refused = krate.run(&["--quiet"]);
+    assert!(stderr(&refused).contains("pass --overwrite"));
+}
+
+#[test]
+fn exclude_warns_about_mandatory_fields() {
+    let krate = Crate::new("");
+
+    let output = krate.run(&["--exclude", "pkgver,url"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--exclude: `pkgver` is mandatory and is still written"));
+    assert!(krate.read("PKGBUILD").contains("\npkgver=1.0.0\n"));
+    assert!(!krate.read("PKGBUILD").contains("\nurl="));
+
+    let unknown = krate.run(&["--exclude", "homepage"]);
+    assert_eq!(unknown.status.code(), Some(1), "{}", stderr(&unknown));
+}
+
+#[test]
+fn print_path_is_where_the_pkgbuild_is_written() {
+    let krate = Crate::new("");
+    krate.write("Cargo.toml", "[workspace]\nmembers = [\"foo\", \"bar\"]\n");
+    for name in &["foo", "bar"] {
+        krate.write(&format!("{}/Cargo.toml", name), &manifest(name, ""));
+        krate.write(&format!("{}/src/main.rs", name), "fn main() {}\n");
+    }
+
+    let output = krate.run(&["--workspace", "--print-path", "--pkgbuild-name", "PKGBUILD.generated"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let mut paths = stdout(&output).lines().map(PathBuf::from).collect::<Vec<PathBuf>>();
+    paths.sort();
+    assert_eq!(paths, [krate.path("bar/PKGBUILD.generated").canonicalize().unwrap(),
+                       krate.path("foo/PKGBUILD.generated").canonicalize().unwrap()]);
+    assert!(!krate.path("PKGBUILD.generated").exists());
+}
+
+#[test]
+fn stdin_renders_to_stdout() {
+    let krate = Crate::new("");
+
+    let output = krate.run_with_stdin(&["--stdin"], &manifest("bar", "pkgrel = \"3\"\n"));
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).starts_with("# Generated by cargo-arch"));
+    assert!(stdout(&output).contains("\npkgname=bar\n"));
+    assert!(stdout(&output).contains("\npkgrel=3\n"));
+    assert!(!krate.path("PKGBUILD").exists());
+
+    let local = krate.run_with_stdin(&["--stdin"], &manifest("bar", "source = [\"bar.service\"]\n"));
+    assert_eq!(local.status.code(), Some(1), "{}", stderr(&local));
+    assert!(stderr(&local).contains("local source `bar.service` can't be found without a manifest directory"));
+    assert_eq!(stdout(&local), "");
+
+    assert!(!krate.run_with_stdin(&["--stdin", "--checksums"], &manifest("bar", "")).status.success());
+}
+
+#[test]
+fn stdout_carries_only_the_pkgbuild() {
+    let krate = Crate::new("");
+    krate.write("Cargo.toml", &krate.read("Cargo.toml").replace("\"A foo\"", "\"A foo.\""));
+
+    let output = krate.run(&["--stdout", "--verbose"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stderr(&output).contains("pkgdesc: ends with a period"));
+    assert!(stdout(&output).starts_with("# Generated by cargo-arch"));
+    assert!(stderr(&output).contains("cargo-arch: debug: "));
+    assert!(!stdout(&output).contains("cargo-arch: "), "{}", stdout(&output));
+    assert!(!krate.path("PKGBUILD").exists());
+}
+
+#[test]
+fn expect_hash_compares_the_rendered_pkgbuild() {
+    let krate = Crate::new("");
+    let rendered = krate.run(&["--stdout"]);
+    assert!(rendered.status.success(), "{}", stderr(&rendered));
+    let mut sha256sum = Command::new("sha256sum").stdin(Stdio::piped()).stdout(Stdio::piped()).spawn().unwrap();
+    std::io::Write::write_all(sha256sum.stdin.as_mut().unwrap(), &rendered.stdout).unwrap();
+    let hash = stdout(&sha256sum.wait_with_output().unwrap())[..64].to_string();
+
+    let matching = krate.run(&["--expect-hash", &hash.to_uppercase()]);
+    assert!(matching.status.success(), "{}", stderr(&matching));
+    assert!(krate.path("PKGBUILD").is_file());
+    fs::remove_file(krate.path("PKGBUILD")).unwrap();
+
+    let mismatching = krate.run(&["--expect-hash", &"0".repeat(64)]);
+    assert_eq!(mismatching.status.code(), Some(1), "{}", stderr(&mismatching));
+    assert!(stderr(&mismatching).contains(&format!("rendered PKGBUILD has sha256 {}, expected {}", hash, "0".repeat(64))));
+    assert!(!krate.path("PKGBUILD").exists());
+}
+
+#[test]
+fn color_never_prints_no_ansi_codes() {
+    let krate = Crate::new("");
+    krate.write("Cargo.toml", &krate.read("Cargo.toml").replace("\"A foo\"", "\"A foo.\""));
+
+    let always = krate.run(&["--stdout", "--color", "always"]);
+    assert!(stderr(&always).contains("cargo-arch: \x1b[33mwarning\x1b[0m: pkgdesc: ends with a period"),
+            "{}", stderr(&always));
+
+    let never = krate.run(&["--stdout", "--color", "never"]);
+    assert!(stderr(&never).contains("cargo-arch: warning: pkgdesc: ends with a period"));
+    assert!(!stderr(&never).contains('\x1b'));
+    assert!(!stdout(&never).contains('\x1b'));
+
+    let validate = krate.run(&["validate", "--color", "never"]);
+    assert!(stdout(&validate).contains("cargo-arch: warning: pkgdesc: ends with a period"), "{}", stdout(&validate));
+    assert!(!stdout(&validate).contains('\x1b'));
+    assert!(!stderr(&validate).contains('\x1b'));
+}
+
+#[test]
+fn jobs_reach_make_and_cargo() {
+    let krate = Crate::new("");
+    // a makepkg recording the job settings it runs with, next to the PKGBUILD
+    let path = krate.stub_makepkg("echo \"MAKEFLAGS=$MAKEFLAGS CARGO_BUILD_JOBS=$CARGO_BUILD_JOBS\" > jobs\n");
+    let build = |args: &[&str]| {
+        krate.build_command(args)
+             .env("PATH", &path)
+             .env_remove("MAKEFLAGS")
+             .env_remove("CARGO_BUILD_JOBS")
+             .output()
+             .unwrap()
+    };
+
+    let output = build(&["--jobs", "4"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(krate.read("jobs"), "MAKEFLAGS=-j4 CARGO_BUILD_JOBS=4\n");
+
+    let output = build(&[]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(krate.read("jobs"), "MAKEFLAGS= CARGO_BUILD_JOBS=\n");
+
+    assert_eq!(build(&["--jobs", "0"]).status.code(), Some(1));
+}
+
+#[test]
+fn output_is_identical_across_runs() {
+    let krate = Crate::new("validpgpkeys = [\"AAAA\", \"BBBB\", \"CCCC\"]\ndepends_from = \"all-deps\"\n\
+                            [package.metadata.arch.pgpkeys]\nCCCC = \"Cid\"\nAAAA = \"Ann\"\nBBBB = \"Bob\"\n\
+                            [package.metadata.arch.depmap]\nzstd = \"zstd\"\nopenssl = \"openssl\"\nxz2 = \"xz\"\n\
+                            \n[dependencies]\nxz2 = \"0.1\"\nzstd = \"0.13\"\nopenssl = \"0.10\"\n");
+
+    let first = krate.run(&["--stdout", "--verbose"]);
+    assert!(first.status.success(), "{}", stderr(&first));
+    for _ in 0..5 {
+        let again = krate.run(&["--stdout", "--verbose"]);
+        assert_eq!(stdout(&again), stdout(&first));
+        assert_eq!(stderr(&again), stderr(&first), "the provenance is logged in the same order");
+    }
+}
+
+#[test]
+fn dump_template_prints_the_embedded_template() {
+    let krate = Crate::new("");
+
+    let default = krate.run(&["dump-template"]);
+    assert!(default.status.success(), "{}", stderr(&default));
+    assert_eq!(stdout(&default), include_str!("../src/config/PKGBUILD-TEMPLATE"));
+
+    let lib = krate.run(&["dump-template", "--name", "lib"]);
+    assert_eq!(stdout(&lib), include_str!("../src/config/PKGBUILD-TEMPLATE-lib"));
+
+    let unknown = krate.run(&["dump-template", "--name", "rpm"]);
+    assert_eq!(unknown.status.code(), Some(1));
+    assert!(stderr(&unknown).contains("unknown template `rpm`, expected one of default, bin, lib, git, cargo-install"),
+            "{}", stderr(&unknown));
+}
+
+#[test]
+fn no_final_newline_leaves_the_line_ending_out() {
+    let krate = Crate::new("");
+
+    assert!(krate.run(&[]).status.success());
+    assert!(krate.read("PKGBUILD").ends_with("}\n") && !krate.read("PKGBUILD").ends_with("\n\n"));
+
+    assert!(krate.run(&["--no-final-newline"]).status.success());
+    assert!(krate.read("PKGBUILD").ends_with('}'));
+}
+
+#[test]
+fn exit_codes_tell_the_failure_class() {
+    let krate = Crate::new("");
+    let path = krate.stub_makepkg("exit 4\n");
+
+    krate.write("Cargo.toml", &manifest("foo", "arch = []\n").replace("arch = [\"x86_64\"]\n", ""));
+    let validation = krate.run(&[]);
+    assert_eq!(validation.status.code(), Some(1), "{}", stderr(&validation));
+    assert!(stderr(&validation).contains("arch: is empty"));
+
+    krate.write("Cargo.toml", "[package\nname = \"foo\"\n");
+    let io = krate.run(&[]);
+    assert_eq!(io.status.code(), Some(2), "{}", stderr(&io));
+    assert!(stderr(&io).contains("could not decode"), "{}", stderr(&io));
+
+    krate.write("Cargo.toml", &manifest("foo", "depends = \"glibc\"\n"));
+    let io = krate.run(&[]);
+    assert_eq!(io.status.code(), Some(2), "{}", stderr(&io));
+    assert!(stderr(&io).contains("could not decode [package.metadata.arch]"), "{}", stderr(&io));
+
+    krate.write("Cargo.toml", &manifest("foo", ""));
+    let io = krate.run(&["--append-to", "missing/PKGBUILD"]);
+    assert_eq!(io.status.code(), Some(2), "{}", stderr(&io));
+    assert!(stderr(&io).contains("--append-to: could not read"), "{}", stderr(&io));
+
+    krate.write("Cargo.toml", &manifest("foo", ""));
+    let tool = krate.build_command(&[]).env("PATH", &path).output().unwrap();
+    assert_eq!(tool.status.code(), Some(3), "{}", stderr(&tool));
+    assert!(stderr(&tool).contains("foo: makepkg failed"), "{}", stderr(&tool));
+}
+
+#[test]
+fn failed_download_is_not_written_back() {
+    let krate = Crate::new("source = [\"file:///nonexistent/foo.tar.gz\"]\nsha256sums = [\"0123abcd\"]\n");
+    let before = krate.read("Cargo.toml");
+
+    let output = krate.command(&["--checksums", "--download", "--write-back"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("--write-back: not writing sha256sums back"), "{}", stderr(&output));
+    assert_eq!(krate.read("Cargo.toml"), before);
+}
+
+#[test]
+fn reproducible_sets_source_date_epoch() {
+    let krate = Crate::new("");
+    let path = krate.stub_makepkg("echo \"SOURCE_DATE_EPOCH=$SOURCE_DATE_EPOCH $*\" > build\n");
+    let build = |args: &[&str]| {
+        krate.build_command(args).env("PATH", &path).env_remove("SOURCE_DATE_EPOCH").output().unwrap()
+    };
+
+    let output = build(&["--reproducible", "1700000000"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(krate.read("build"), "SOURCE_DATE_EPOCH=1700000000 -p PKGBUILD --cleanbuild\n");
+
+    let no_commit = build(&["--reproducible"]);
+    assert_eq!(no_commit.status.code(), Some(1), "{}", stderr(&no_commit));
+    assert!(stderr(&no_commit).contains("no git commit to take SOURCE_DATE_EPOCH from"));
+
+    assert_eq!(build(&["--reproducible", "yesterday"]).status.code(), Some(1));
+}
+
+#[test]
+fn misspelled_key_suggests_the_field() {
+    let krate = Crate::new("pkgdescription = \"Foo tool\"\n");
+
+    let output = krate.run(&[]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("cargo-arch: error: metadata: unknown key `pkgdescription` in \
+                                      [package.metadata.arch], did you mean `pkgdesc`?"),
+            "{}", stderr(&output));
+    assert!(!krate.path("PKGBUILD").exists());
+
+    let lenient = krate.run(&["--lenient"]);
+    assert!(lenient.status.success(), "{}", stderr(&lenient));
+    assert!(stderr(&lenient).contains("cargo-arch: warning: metadata: unknown key `pkgdescription`"));
+    assert!(krate.read("PKGBUILD").contains("\npkgdesc=\"A foo\"\n"));
+}
+
+#[test]
+fn arch_from_the_environment() {
+    let krate = Crate::new("");
+
+    let output = krate.command(&[]).env("CARGO_ARCH_ARCH", "aarch64").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.read("PKGBUILD").contains("\narch=(\"aarch64\")\n"));
+
+    let output = krate.command(&[]).env("CARGO_ARCH_ARCH", "x86_64, aarch64").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.read("PKGBUILD").contains("\narch=(\"x86_64\", \"aarch64\")\n"));
+
+    let output = krate.command(&["--verbose", "--pkgrel", "3"]).env("CARGO_ARCH_ARCH", "aarch64").output().unwrap();
+    assert!(stderr(&output).contains("foo: arch from Environment"), "{}", stderr(&output));
+    assert!(stderr(&output).contains("foo: pkgrel from CommandLine"), "{}", stderr(&output));
+    let output = krate.command(&["--verbose"]).env("CARGO_ARCH_PKGREL", "2").output().unwrap();
+    assert!(stderr(&output).contains("foo: pkgrel from Environment"), "{}", stderr(&output));
+
+    let output = krate.command(&[]).env("CARGO_ARCH_ARCH", "arm64").output().unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("`arm64` from CARGO_ARCH_ARCH is not an Arch Linux architecture"));
+}
+
+#[test]
+fn pkgrel_flag_wins_over_every_default() {
+    let krate = Crate::new("default_pkgrel = \"4\"\n");
+
+    let output = krate.command(&["--pkgrel", "3"]).env("CARGO_ARCH_PKGREL", "2").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.read("PKGBUILD").contains("\npkgrel=3\n"));
+
+    krate.write("Cargo.toml", &manifest("foo", "pkgrel = \"5\"\n"));
+    let output = krate.command(&["--pkgrel", "3"]).env("CARGO_ARCH_PKGREL", "2").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.read("PKGBUILD").contains("\npkgrel=3\n"));
+
+    let output = krate.command(&[]).env("CARGO_ARCH_PKGREL", "2").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(krate.read("PKGBUILD").contains("\npkgrel=5\n"));
+}
+
+#[test]
+fn pkgrel_from_the_environment_is_validated() {
+    let krate = Crate::new("");
+
+    let output = krate.command(&[]).env("CARGO_ARCH_PKGREL", "abc").output().unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(stderr(&output).contains("pkgrel: `abc` is not a release number"), "{}", stderr(&output));
+}
+
+#[test]
+fn list_fields_prints_the_known_fields() {
+    let krate = Crate::new("");
+
+    let output = krate.run(&["list-fields"]);
+    assert!(output.status.success(), "{}", stderr(&output));
+    let listing = stdout(&output);
+    let fields = listing.lines()
+                        .map(|line| line.split_whitespace().collect::<Vec<&str>>())
+                        .collect::<Vec<Vec<&str>>>();
+    let field = |name: &str| fields.iter().find(|words| words[0] == name).cloned().unwrap_or_default();
+
+    assert_eq!(field("pkgdesc")[..2], ["pkgdesc", "string"]);
+    assert_eq!(field("depends")[..4], ["depends", "array", "of", "strings"]);
+    assert_eq!(field("auto_makedepends")[..2], ["auto_makedepends", "bool"]);
+    assert_eq!(field("deps"), ["deps", "alias", "of", "depends"]);
+    assert!(field("pkgdescription").is_empty());
+}